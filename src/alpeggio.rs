@@ -0,0 +1,307 @@
+use crate::{
+    commands::{
+        commands_mml::{NoteR, Tie},
+        commands_note_effect::Alpeggio,
+    },
+    errors::SemanticError,
+    events::{Clock, Event, EventKind},
+    length::LengthState,
+    models::DivisorClock,
+    part_command::{PartCommand, PartCommandStruct},
+    pitch::Pitch,
+};
+
+// 音長2 omitted
+pub const DEFAULT_STEP: u8 = 1;
+
+// resolves 音長2 into clocks, 音長1 is already resolved into `clocks`
+pub fn normalize(
+    alpeggio: &Alpeggio,
+    clocks: Clock,
+    state: &LengthState,
+) -> Result<Alpeggio, SemanticError> {
+    let step = match &alpeggio.length2 {
+        Some(length) => state.resolve(Some(length), 0)?,
+        None => DEFAULT_STEP as Clock,
+    };
+
+    // 音長2 and 数値2 must be shorter than 音長1
+    let max = clocks.saturating_sub(1) as i32;
+    if step as Clock >= clocks {
+        return Err(SemanticError::OutOfRange(
+            alpeggio.command_begin.clone(),
+            step as i32,
+            1,
+            max,
+        ));
+    }
+    if let Some(gate) = alpeggio.value2
+        && gate as Clock >= clocks
+    {
+        return Err(SemanticError::OutOfRange(
+            alpeggio.command_begin.clone(),
+            gate as i32,
+            0,
+            max,
+        ));
+    }
+
+    let mut alpeggio = alpeggio.clone();
+    alpeggio.length2 = Some(DivisorClock::Clock(step as u8));
+    Ok(alpeggio)
+}
+
+// expands {{ }} into notes of 音長2 cycling through `notes`, each with its resolved pitch.
+// the generated events keep the code of {{ and are marked as split
+pub fn expand(event: &Event, notes: &[(PartCommand, Pitch)]) -> Vec<Event> {
+    let EventKind::Note {
+        command: PartCommand::Alpeggio(alpeggio),
+        clocks,
+        ..
+    } = &event.kind
+    else {
+        return vec![event.clone()];
+    };
+
+    if notes.is_empty() {
+        return vec![event.clone()];
+    }
+
+    let step = match &alpeggio.length2 {
+        Some(DivisorClock::Clock(step)) => (*step).max(1) as Clock,
+        _ => DEFAULT_STEP as Clock,
+    };
+    let rest = (alpeggio.value2.unwrap_or(0) as Clock).min(*clocks);
+    let volume = alpeggio.value3.unwrap_or(0);
+
+    let mut events = vec![];
+    let mut remaining = clocks - rest;
+    let mut index = 0;
+
+    while remaining > 0 {
+        let length = step.min(remaining);
+        let (command, pitch) = &notes[index];
+        events.push(Event::split(
+            &event.code,
+            EventKind::Note {
+                command: command.clone(),
+                clocks: length,
+                pitch: Some(*pitch),
+                gate: None,
+                legato: false,
+                volume: None,
+                portamento: None,
+            },
+        ));
+        remaining -= length;
+
+        // 数値1, & after each note
+        if alpeggio.value1 && remaining > 0 {
+            events.push(Event::split(&event.code, EventKind::Command(tie())));
+        }
+
+        // 数値3, the changed volume stays after the command
+        index += 1;
+        if index == notes.len() {
+            index = 0;
+            if volume != 0 {
+                events.push(Event::split(&event.code, EventKind::VolumeShift(volume)));
+            }
+        }
+    }
+
+    // 数値2, the last part is a rest
+    if rest > 0 {
+        let command = NoteR {
+            command: "r".to_string(),
+            length: Some(DivisorClock::Clock(rest as u8)),
+            dots: 0,
+        };
+        events.push(Event::split(
+            &event.code,
+            EventKind::Rest {
+                command: command.to_variant(),
+                clocks: rest,
+            },
+        ));
+    }
+
+    events
+}
+
+fn tie() -> PartCommand {
+    Tie {
+        command: "&".to_string(),
+        length: None,
+        dots: None,
+    }
+    .to_variant()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        commands::commands_mml::{Note, OctaveUpDown},
+        diagnostics::Diagnostic,
+        gate::GateResolver,
+        length::LengthResolver,
+        meta_models::{Code, Pass2Result},
+        models::PartSymbol,
+        options::CompileOptions,
+        part_command::WrappedPartCommand,
+        pitch::PitchResolver,
+        tie::TieMerger,
+    };
+
+    use super::*;
+
+    fn alpeggio(
+        notes: Vec<PartCommand>,
+        length1: u8,
+        length2: Option<DivisorClock<u8>>,
+        tie: bool,
+        gate: Option<u8>,
+        volume: Option<i8>,
+    ) -> PartCommand {
+        let code = Code::default();
+        Alpeggio {
+            command_begin: "{{".to_string(),
+            notes: notes
+                .into_iter()
+                .map(|c| WrappedPartCommand::new(&code, c))
+                .collect(),
+            command_end: "}}".to_string(),
+            length1: Some(DivisorClock::Divisor(length1)),
+            dots: 0,
+            length2,
+            value1: tie,
+            value2: gate,
+            value3: volume,
+        }
+        .to_variant()
+    }
+
+    fn note(command: &str) -> PartCommand {
+        Note {
+            command: command.to_string(),
+            natural: false,
+            semitone: None,
+            length: None,
+            dots: 0,
+        }
+        .to_variant()
+    }
+
+    fn resolve(commands: Vec<PartCommand>) -> (Vec<Event>, Vec<Diagnostic>) {
+        let code = Code::default();
+        let mut pass2 = Pass2Result::default();
+        pass2.parts.push((
+            PartSymbol::A,
+            commands
+                .into_iter()
+                .map(|c| WrappedPartCommand::new(&code, c))
+                .collect(),
+        ));
+
+        let options = CompileOptions::default();
+        let (mut parts, mut diagnostics) = LengthResolver::new(&pass2, &options).resolve();
+        diagnostics.extend(PitchResolver::new(&pass2).resolve(&mut parts));
+        diagnostics.extend(GateResolver::new(&pass2, &options).resolve(&mut parts));
+        diagnostics.extend(TieMerger::new().resolve(&mut parts));
+
+        (parts.remove(0).1, diagnostics)
+    }
+
+    fn notes(events: &[Event]) -> Vec<(Pitch, Clock, bool)> {
+        events
+            .iter()
+            .filter_map(|e| match &e.kind {
+                EventKind::Note {
+                    pitch: Some(pitch),
+                    clocks,
+                    legato,
+                    ..
+                } => Some((*pitch, *clocks, *legato)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_expand_tie() {
+        let octave_up = OctaveUpDown {
+            command: ">".to_string(),
+        }
+        .to_variant();
+        let (events, diagnostics) = resolve(vec![
+            alpeggio(
+                vec![note("c"), note("e"), octave_up, note("c")],
+                4,
+                Some(DivisorClock::Clock(5)),
+                true,
+                None,
+                None,
+            ),
+            note("c"),
+        ]);
+
+        assert!(diagnostics.is_empty());
+        assert!(events[..events.len() - 1].iter().all(|e| e.split));
+        assert_eq!(
+            vec![
+                (60, 5, true),
+                (64, 5, true),
+                (72, 5, true),
+                (60, 5, true),
+                (64, 4, false),
+                // octave commands inside {{ }} stay in effect
+                (72, 24, false),
+            ],
+            notes(&events)
+        );
+    }
+
+    #[test]
+    fn test_expand_gate_volume() {
+        let (events, diagnostics) = resolve(vec![alpeggio(
+            vec![note("c"), note("e")],
+            4,
+            Some(DivisorClock::Divisor(16)),
+            false,
+            Some(6),
+            Some(-2),
+        )]);
+
+        assert!(diagnostics.is_empty());
+        assert_eq!(
+            vec![(60, 6, false), (64, 6, false), (60, 6, false)],
+            notes(&events)
+        );
+        assert_eq!(EventKind::VolumeShift(-2), events[2].kind);
+        assert!(matches!(events[4].kind, EventKind::Rest { clocks: 6, .. }));
+        assert_eq!(5, events.len());
+    }
+
+    #[test]
+    fn test_normalize_errors() {
+        let (_, diagnostics) = resolve(vec![
+            alpeggio(
+                vec![note("c")],
+                8,
+                Some(DivisorClock::Divisor(4)),
+                true,
+                None,
+                None,
+            ),
+            alpeggio(vec![note("c")], 8, None, true, Some(12), None),
+        ]);
+
+        assert_eq!(
+            vec![
+                SemanticError::OutOfRange("{{".to_string(), 24, 1, 11),
+                SemanticError::OutOfRange("{{".to_string(), 12, 0, 11),
+            ],
+            diagnostics.into_iter().map(|d| d.error).collect::<Vec<_>>()
+        );
+    }
+}
@@ -1,3 +1,4 @@
+use crate::meta_models::TokenTrait;
 use crate::part_command::{PartCommandParseState, WrappedPartCommand};
 use crate::utils::get_type_name;
 use crate::{
@@ -135,7 +136,7 @@ impl PartCommandStruct for NoteX {
     }
 
     fn is_match(command: &str) -> bool {
-        vec!["o", "o+", "o-"].contains(&command)
+        ["o", "o+", "o-"].contains(&command)
     }
 
     fn parse(working: &mut crate::meta_models::Pass2Working, c: char) -> PartCommandParseState {
@@ -199,7 +200,34 @@ impl PartCommandStruct for NoteR {
     }
 
     fn parse(working: &mut crate::meta_models::Pass2Working, c: char) -> PartCommandParseState {
-        todo!()
+        match c {
+            '%' | '0'..='9' => {
+                // length, optional
+                if working.state > 1 {
+                    panic!("NoteR: unexpected {c}");
+                }
+
+                working.eat(c);
+                working.jump(1);
+            }
+            '.' => {
+                // dots, optional
+                if working.state == 1 {
+                    working.push();
+                }
+
+                working.eat(c);
+                working.jump(2);
+            }
+            _ => {
+                // other command
+                working.push();
+
+                return PartCommandParseState::Parsed;
+            }
+        };
+
+        PartCommandParseState::Parsing
     }
 }
 
@@ -239,11 +267,45 @@ impl PartCommandStruct for Portamento {
     }
 
     fn is_match(command: &str) -> bool {
-        todo!()
+        command == "{"
     }
 
     fn parse(working: &mut crate::meta_models::Pass2Working, c: char) -> PartCommandParseState {
-        todo!()
+        match c {
+            '0'..='9' => {
+                // length1 and length2, optional
+                match working.state {
+                    1 => working.jump(2),
+                    2 | 4 => {}
+                    _ => panic!("{}: unexpected {c}", get_type_name::<Portamento>()),
+                }
+
+                working.eat(c);
+            }
+            '.' => {
+                // dots, optional
+                match working.state {
+                    2 => working.push(),
+                    3 => {}
+                    _ => panic!("{}: unexpected {c}", get_type_name::<Portamento>()),
+                }
+
+                working.eat(c);
+                working.jump(3);
+            }
+            ',' if (1..=3).contains(&working.state) => {
+                working.push();
+                working.jump(4);
+            }
+            _ => {
+                // other command
+                working.push();
+
+                return PartCommandParseState::Parsed;
+            }
+        }
+
+        PartCommandParseState::Parsing
     }
 }
 
@@ -257,7 +319,7 @@ impl TryFrom<PartTokenStack> for Portamento {
         } else {
             panic!("TryFrom for Portamento: unexpected empty stack");
         };
-        let end_command = try_from_get_value!(value.pop_and_cast(1), begin_command);
+        let end_command = try_from_get_value!(value.pop_and_cast(1), end_command);
 
         let pitch = maybe_pitch
             .iter()
@@ -307,7 +369,7 @@ impl PartCommandStruct for Octave {
     }
 
     fn is_match(command: &str) -> bool {
-        vec!["o", "o+", "o-"].contains(&command)
+        ["o", "o+", "o-"].contains(&command)
     }
 
     fn parse(working: &mut crate::meta_models::Pass2Working, c: char) -> PartCommandParseState {
@@ -371,7 +433,7 @@ impl PartCommandStruct for OctaveUpDown {
     }
 
     fn is_match(command: &str) -> bool {
-        vec![">", "<"].contains(&command)
+        [">", "<"].contains(&command)
     }
 
     fn parse(working: &mut crate::meta_models::Pass2Working, c: char) -> PartCommandParseState {
@@ -416,11 +478,11 @@ impl PartCommandStruct for OctaveReverse {
         false
     }
 
-    fn is_match(command: &str) -> bool {
+    fn is_match(_command: &str) -> bool {
         todo!()
     }
 
-    fn parse(working: &mut crate::meta_models::Pass2Working, c: char) -> PartCommandParseState {
+    fn parse(_working: &mut crate::meta_models::Pass2Working, _c: char) -> PartCommandParseState {
         todo!()
     }
 }
@@ -480,7 +542,7 @@ impl PartCommandStruct for DefaultLength {
             }
             '0'..='9' => {
                 // length, required
-                if working.state > 2 {
+                if working.state > 3 {
                     panic!("{}: unexpected {c}", get_type_name::<DefaultLength>());
                 }
 
@@ -517,8 +579,8 @@ impl TryFrom<PartTokenStack> for DefaultLength {
 
     fn try_from(mut value: PartTokenStack) -> Result<Self, Self::Error> {
         let command = try_from_get_value!(value.pop_and_cast(0), command);
-        let length = make_some_length(value.pop_by_state_all(1));
-        let dots = count_dots(try_from_get_some_value!(value.pop_and_cast(2), dots));
+        let length = make_some_length(value.pop_by_state_all(3));
+        let dots = count_dots(try_from_get_some_value!(value.pop_and_cast(4), dots));
 
         Ok(DefaultLength {
             command,
@@ -528,6 +590,67 @@ impl TryFrom<PartTokenStack> for DefaultLength {
     }
 }
 
+// ===============================================================================
+// §4-11	全音符長設定
+// 	C
+// -------------------------------------------------------------------------------
+// [書式]	C 数値
+// -------------------------------------------------------------------------------
+// [範囲]	1～255
+// -------------------------------------------------------------------------------
+// [音源]	FM / SSG / PCM / R選択 / R定義
+// -------------------------------------------------------------------------------
+// 	全音符の長さを clock 値で指定します。デフォルトは 96 です。(->§2-11)
+// 	以降の音長は、この数値の約数でなくてはなりません。
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct WholeLength {
+    pub command: String,
+    pub value: u8,
+}
+
+impl PartCommandStruct for WholeLength {
+    fn to_variant(self) -> PartCommand {
+        PartCommand::WholeLength(self)
+    }
+
+    fn is_block() -> bool {
+        false
+    }
+
+    fn is_match(command: &str) -> bool {
+        command == "C"
+    }
+
+    fn parse(working: &mut crate::meta_models::Pass2Working, c: char) -> PartCommandParseState {
+        match c {
+            '0'..='9' => {
+                // value, required
+                working.eat(c);
+                working.jump(1);
+            }
+            _ => {
+                // other command
+                working.push();
+
+                return PartCommandParseState::Parsed;
+            }
+        }
+
+        PartCommandParseState::Parsing
+    }
+}
+
+impl TryFrom<PartTokenStack> for WholeLength {
+    type Error = Pass2Error;
+
+    fn try_from(mut value: PartTokenStack) -> Result<Self, Self::Error> {
+        let command = try_from_get_value!(value.pop_and_cast(0), command);
+        let value = try_from_get_value!(value.pop_and_cast::<u8>(1), value);
+
+        Ok(WholeLength { command, value })
+    }
+}
+
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct ProcessLastLengthUpdate {
     pub command: String,
@@ -546,11 +669,38 @@ impl PartCommandStruct for ProcessLastLengthUpdate {
     }
 
     fn is_match(command: &str) -> bool {
-        todo!()
+        command == "l="
     }
 
     fn parse(working: &mut crate::meta_models::Pass2Working, c: char) -> PartCommandParseState {
-        todo!()
+        match c {
+            '%' | '0'..='9' => {
+                // length, optional
+                if working.state > 2 {
+                    panic!("{}: unexpected {c}", get_type_name::<ProcessLastLengthUpdate>());
+                }
+
+                working.eat(c);
+                working.jump(2);
+            }
+            '.' => {
+                // dots, optional
+                if working.state == 2 {
+                    working.push();
+                }
+
+                working.eat(c);
+                working.jump(3);
+            }
+            _ => {
+                // other command
+                working.push();
+
+                return PartCommandParseState::Parsed;
+            }
+        };
+
+        PartCommandParseState::Parsing
     }
 }
 
@@ -583,7 +733,7 @@ pub struct ProcessLastLengthAddSub {
 impl PartCommandStruct for ProcessLastLengthAddSub {
     fn to_variant(self) -> PartCommand {
         match self.command.as_str() {
-            "l+" => PartCommand::ProcessLastLengthAdd(self),
+            "l+" | "^" => PartCommand::ProcessLastLengthAdd(self),
             "l-" => PartCommand::ProcessLastLengthSubtract(self),
             _ => {
                 panic!("unexpected command: {}", self.command);
@@ -596,11 +746,38 @@ impl PartCommandStruct for ProcessLastLengthAddSub {
     }
 
     fn is_match(command: &str) -> bool {
-        todo!()
+        ["^", "l+", "l-"].contains(&command)
     }
 
     fn parse(working: &mut crate::meta_models::Pass2Working, c: char) -> PartCommandParseState {
-        todo!()
+        match c {
+            '%' | '0'..='9' => {
+                // length, optional
+                if working.state > 3 {
+                    panic!("{}: unexpected {c}", get_type_name::<ProcessLastLengthAddSub>());
+                }
+
+                working.eat(c);
+                working.jump(3);
+            }
+            '.' => {
+                // dots, optional
+                if working.state == 3 {
+                    working.push();
+                }
+
+                working.eat(c);
+                working.jump(4);
+            }
+            _ => {
+                // other command
+                working.push();
+
+                return PartCommandParseState::Parsed;
+            }
+        };
+
+        PartCommandParseState::Parsing
     }
 }
 
@@ -636,12 +813,72 @@ impl PartCommandStruct for ProcessLastLengthMultiply {
     }
 
     fn is_match(command: &str) -> bool {
-        todo!()
+        command == "l^"
     }
 
     fn parse(working: &mut crate::meta_models::Pass2Working, c: char) -> PartCommandParseState {
-        todo!()
+        match c {
+            '0'..='9' => {
+                // value, required
+                working.eat(c);
+                working.jump(1);
+            }
+            _ => {
+                // other command
+                working.push();
+
+                return PartCommandParseState::Parsed;
+            }
+        }
+
+        PartCommandParseState::Parsing
+    }
+}
+
+impl TryFrom<PartTokenStack> for ProcessLastLengthMultiply {
+    type Error = Pass2Error;
+
+    fn try_from(mut value: PartTokenStack) -> Result<Self, Self::Error> {
+        let command = try_from_get_value!(value.pop_and_cast(0), command);
+        let value = try_from_get_value!(value.pop_and_cast::<u8>(1), value);
+
+        Ok(ProcessLastLengthMultiply { command, value })
+    }
+}
+
+// & and && take an optional length of the tied note, as c4&8
+fn parse_tie_length(
+    working: &mut crate::meta_models::Pass2Working,
+    c: char,
+) -> PartCommandParseState {
+    match c {
+        '0'..='9' => {
+            // length, optional
+            if working.state > 2 {
+                panic!("{}: unexpected {c}", get_type_name::<Tie>());
+            }
+
+            working.eat(c);
+            working.jump(2);
+        }
+        '.' if working.state == 2 || working.state == 3 => {
+            // dots, optional
+            if working.state == 2 {
+                working.push();
+            }
+
+            working.eat(c);
+            working.jump(3);
+        }
+        _ => {
+            // other command
+            working.push();
+
+            return PartCommandParseState::Parsed;
+        }
     }
+
+    PartCommandParseState::Parsing
 }
 
 #[derive(Debug, Clone, Eq, PartialEq)]
@@ -661,11 +898,27 @@ impl PartCommandStruct for Tie {
     }
 
     fn is_match(command: &str) -> bool {
-        todo!()
+        command == "&"
     }
 
     fn parse(working: &mut crate::meta_models::Pass2Working, c: char) -> PartCommandParseState {
-        todo!()
+        parse_tie_length(working, c)
+    }
+}
+
+impl TryFrom<PartTokenStack> for Tie {
+    type Error = Pass2Error;
+
+    fn try_from(mut value: PartTokenStack) -> Result<Self, Self::Error> {
+        let command = try_from_get_value!(value.pop_and_cast(1), command);
+        let length = try_from_get_some_value!(value.pop_and_cast::<u8>(2), length);
+        let dots = try_from_get_some_value!(value.pop_and_cast(3), dots);
+
+        Ok(Tie {
+            command,
+            length,
+            dots,
+        })
     }
 }
 
@@ -686,11 +939,27 @@ impl PartCommandStruct for Slur {
     }
 
     fn is_match(command: &str) -> bool {
-        todo!()
+        command == "&&"
     }
 
     fn parse(working: &mut crate::meta_models::Pass2Working, c: char) -> PartCommandParseState {
-        todo!()
+        parse_tie_length(working, c)
+    }
+}
+
+impl TryFrom<PartTokenStack> for Slur {
+    type Error = Pass2Error;
+
+    fn try_from(mut value: PartTokenStack) -> Result<Self, Self::Error> {
+        let command = try_from_get_value!(value.pop_and_cast(1), command);
+        let length = try_from_get_some_value!(value.pop_and_cast::<u8>(2), length);
+        let dots = try_from_get_some_value!(value.pop_and_cast(3), dots);
+
+        Ok(Slur {
+            command,
+            length,
+            dots,
+        })
     }
 }
 
@@ -717,9 +986,10 @@ impl PartCommandStruct for Quantize1 {
     fn parse(working: &mut crate::meta_models::Pass2Working, c: char) -> PartCommandParseState {
         match c {
             '%' => {
+                // Q%数値, optional
+                working.jump(2);
                 working.eat(c);
                 working.push();
-                working.jump(2);
             }
             '0'..='9' => {
                 working.eat(c);
@@ -741,10 +1011,12 @@ impl TryFrom<PartTokenStack> for Quantize1 {
     type Error = Pass2Error;
 
     fn try_from(mut value: PartTokenStack) -> Result<Self, Self::Error> {
-        let command = try_from_get_value!(value.pop_and_cast(1), command);
+        let command = try_from_get_value!(value.pop_and_cast(0), command);
         // let divisor = try_from_get_some_value!(value.pop_and_cast::<DivisorClock<u8>>(2), divisor);
-        let divisor = None;
+        let percent = try_from_get_some_value!(value.pop_and_cast::<String>(2), divisor);
         let value = try_from_get_value!(value.pop_and_cast(3), value);
+        // Q%数値
+        let divisor = percent.map(|_| DivisorClock::Clock(value));
 
         Ok(Self {
             command,
@@ -757,6 +1029,8 @@ impl TryFrom<PartTokenStack> for Quantize1 {
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Quantize2 {
     pub command: String,
+    // [書式2] values are note lengths (l)
+    pub by_length: bool,
     pub value1: Option<u8>,
     pub value1_dots: u8,
     pub value2: Option<u8>,
@@ -778,21 +1052,25 @@ impl PartCommandStruct for Quantize2 {
     }
 
     fn parse(working: &mut crate::meta_models::Pass2Working, c: char) -> PartCommandParseState {
-        match c {
-            '-' => {
-                if !(working.state == 1 || working.state == 3) {
-                    panic!("q: unexpected -");
-                }
+        // [書式2] puts l before each value, which moves the values to other states
+        let by_length = working
+            .tokens
+            .get_and_cast::<String>(1)
+            .is_ok_and(|v| v.is_some_and(|v| v == "l"));
 
+        match c {
+            'l' if working.token.is_empty() && matches!(working.state, 1 | 5 | 7) => {
                 working.eat(c);
                 working.push();
                 working.next();
             }
-            'l' => {
-                if !(working.state == 0 || working.state == 4 || working.state == 7) {
-                    panic!("q: unexpected l");
+            '-' => {
+                if !(working.state == 1 || (by_length && matches!(working.state, 2 | 3))) {
+                    panic!("q: unexpected -");
                 }
 
+                working.push();
+                working.jump(if by_length { 4 } else { 2 });
                 working.eat(c);
                 working.push();
                 working.next();
@@ -802,20 +1080,19 @@ impl PartCommandStruct for Quantize2 {
             }
             '.' => {
                 // dots, optional
-                if working.state == 2 || working.state == 8 {
-                    working.push();
-                }
-
-                working.eat(c);
                 match working.state {
-                    2 => working.jump(4),
-                    8 => working.jump(10),
+                    2 | 8 if by_length => {
+                        working.push();
+                        working.jump(if working.state == 2 { 3 } else { 10 });
+                        working.eat(c);
+                    }
+                    3 | 10 if by_length => working.eat(c),
                     _ => panic!("q: unexpected dot"),
-                };
+                }
             }
             ',' => {
                 working.push();
-                working.next();
+                working.jump(if by_length { 7 } else { 4 });
             }
             _ => {
                 // other command
@@ -840,10 +1117,9 @@ impl Quantize2 {
         let value2 = try_from_get_some_value!(value.pop_and_cast(3), value2);
         if value2.is_some()
             && let Some(v) = has_range
+            && !v
         {
-            if !v {
-                panic!("Quantize2 (format 1): unexpected range");
-            }
+            panic!("Quantize2 (format 1): unexpected range");
         }
 
         let value3 = try_from_get_some_value!(value.pop_and_cast(4), value3);
@@ -851,6 +1127,7 @@ impl Quantize2 {
 
         Ok(Self {
             command,
+            by_length: false,
             value1,
             value1_dots,
             value2,
@@ -863,42 +1140,32 @@ impl Quantize2 {
         let command = try_from_get_value!(value.pop_and_cast(0), command);
 
         let has_value1_l =
-            try_from_get_some_value!(value.pop_and_cast::<String>(1), value1_l).map(|v| v != "l");
+            try_from_get_some_value!(value.pop_and_cast::<String>(1), value1_l).map(|v| v == "l");
         let value1 = try_from_get_some_value!(value.pop_and_cast(2), value1);
-        if value1.is_some()
-            && let Some(v) = has_value1_l
-            && !v
-        {
+        if value1.is_some() && has_value1_l != Some(true) {
             panic!("Quantize2 (format 2): l is not specified in value1");
         }
         let value1_dots = count_dots(try_from_get_some_value!(value.pop_and_cast(3), value1_dots));
 
         let has_range =
-            try_from_get_some_value!(value.pop_and_cast::<String>(4), range).map(|v| v == "l");
+            try_from_get_some_value!(value.pop_and_cast::<String>(4), range).map(|v| v == "-");
         let has_value2_l =
-            try_from_get_some_value!(value.pop_and_cast::<String>(5), value1_l).map(|v| v != "l");
+            try_from_get_some_value!(value.pop_and_cast::<String>(5), value2_l).map(|v| v == "l");
         let value2 = try_from_get_some_value!(value.pop_and_cast(6), value2);
         if value2.is_some() {
-            if let Some(v) = has_range
-                && !v
-            {
+            if has_range != Some(true) {
                 panic!("Quantize2 (format 2): unexpected range");
             }
 
-            if let Some(v) = has_value2_l
-                && !v
-            {
+            if has_value2_l != Some(true) {
                 panic!("Quantize2 (format 2): l is not specified in value2");
             }
         }
 
         let has_value3_l =
-            try_from_get_some_value!(value.pop_and_cast::<String>(7), value1_l).map(|v| v != "l");
+            try_from_get_some_value!(value.pop_and_cast::<String>(7), value3_l).map(|v| v == "l");
         let value3 = try_from_get_some_value!(value.pop_and_cast(8), value3);
-        if value1.is_some()
-            && let Some(v) = has_value3_l
-            && !v
-        {
+        if value3.is_some() && has_value3_l != Some(true) {
             panic!("Quantize2 (format 2): l is not specified in value3");
         }
         let value3_dots = count_dots(try_from_get_some_value!(
@@ -908,6 +1175,7 @@ impl Quantize2 {
 
         Ok(Self {
             command,
+            by_length: true,
             value1,
             value1_dots,
             value2,
@@ -954,7 +1222,7 @@ impl PartCommandStruct for TemporaryTranspose {
     }
 
     fn is_match(command: &str) -> bool {
-        vec!["_", "__"].contains(&command)
+        ["_", "__"].contains(&command)
     }
 
     fn parse(working: &mut crate::meta_models::Pass2Working, c: char) -> PartCommandParseState {
@@ -1080,13 +1348,12 @@ impl TryFrom<PartTokenStack> for PartTranspose {
     type Error = Pass2Error;
 
     fn try_from(mut value: PartTokenStack) -> Result<Self, Self::Error> {
-        println!("PartTranspose: {:?}", value);
-
         let command_begin = try_from_get_value!(value.pop_and_cast(1), command);
         let sign = try_from_get_some_value!(value.pop_and_cast::<NegativePositiveEqual>(2), sign);
         let notes = match value.part_command_stack_mut().pop_vec() {
             Some(v) => {
-                if v.len() > 0 {
+                // _{=} resets the key signature
+                if !v.is_empty() || sign == Some(NegativePositiveEqual::Equal) {
                     v
                 } else {
                     panic!("TryFrom for PartTranspose (notes) is empty");
@@ -1199,8 +1466,6 @@ mod tests {
             t.set_state(state);
             *t.chars_mut() = v;
 
-            println!("{:?}", t);
-
             self.push(&t);
         }
     }
@@ -29,7 +29,7 @@ impl PartCommandStruct for Volume {
     }
 
     fn is_match(command: &str) -> bool {
-        vec!["v", "V", "v+", "v-", "v)", "v("].contains(&command)
+        ["v", "V", "v+", "v-", "v)", "v("].contains(&command)
     }
 
     fn parse(working: &mut crate::meta_models::Pass2Working, c: char) -> PartCommandParseState {
@@ -59,3 +59,74 @@ impl TryFrom<PartTokenStack> for Volume {
         Ok(Self { command, value })
     }
 }
+
+// ===============================================================================
+// §5-3	相対音量指定
+// 	) (
+// -------------------------------------------------------------------------------
+// [書式1]	) [数値]
+// [書式2]	( [数値]
+// -------------------------------------------------------------------------------
+// [範囲]	1～255
+// -------------------------------------------------------------------------------
+// [音源]	FM / SSG / PCM / R定義
+// -------------------------------------------------------------------------------
+// 	) の場合、音量を数値分上げます。
+// 	( の場合、音量を数値分下げます。
+//
+// 	数値は V の値で指定します。
+// 	省略した場合は、v の１段階分変化します。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RelativeVolume {
+    pub command: String,
+    pub value: Option<u8>,
+}
+
+impl PartCommandStruct for RelativeVolume {
+    fn to_variant(self) -> PartCommand {
+        match self.command.as_str() {
+            ")" => PartCommand::RelativeVolumeUp(self),
+            "(" => PartCommand::RelativeVolumeDown(self),
+            _ => {
+                panic!("unexpected command: {}", self.command);
+            }
+        }
+    }
+
+    fn is_block() -> bool {
+        false
+    }
+
+    fn is_match(command: &str) -> bool {
+        command == ")" || command == "("
+    }
+
+    fn parse(working: &mut crate::meta_models::Pass2Working, c: char) -> PartCommandParseState {
+        match c {
+            '0'..='9' => {
+                // value, optional
+                working.eat(c);
+                working.jump(2);
+            }
+            _ => {
+                // other command
+                working.push();
+
+                return PartCommandParseState::Parsed;
+            }
+        }
+
+        PartCommandParseState::Parsing
+    }
+}
+
+impl TryFrom<PartTokenStack> for RelativeVolume {
+    type Error = Pass2Error;
+
+    fn try_from(mut value: PartTokenStack) -> Result<Self, Self::Error> {
+        let command = try_from_get_value!(value.pop_and_cast(0), command);
+        let value = try_from_get_some_value!(value.pop_and_cast::<u8>(2), value);
+
+        Ok(Self { command, value })
+    }
+}
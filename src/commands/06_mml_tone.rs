@@ -0,0 +1,136 @@
+use crate::{
+    errors::Pass2Error,
+    models::NegativePositive,
+    part_command::{PartCommand, PartCommandParseState, PartCommandStruct, PartTokenStack},
+};
+
+// ===============================================================================
+// §6-1	音色番号指定
+// 	@
+// -------------------------------------------------------------------------------
+// [書式1]	@[@] 音色番号
+// [書式2]	@[@] 音色番号[,数値1[,数値2[,数値3]]]
+// -------------------------------------------------------------------------------
+// [範囲]	音色番号	FM,PCM	0～255
+// 	音色番号	SSG	0～9
+// 	音色番号	SSGﾘｽﾞﾑ	0～16383
+// 	数値1		PCM	-32768～+32767
+// 	数値2		PCM	-32768～+32767
+// 	数値3		PCM	-32768～+32767
+// -------------------------------------------------------------------------------
+// [音源]	FM / SSG / PCM / R定義
+// -------------------------------------------------------------------------------
+// 	@@ と表記された場合は、音色番号に128が加算されます。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ToneNumber {
+    pub command: String,
+    pub value: u16,
+    pub repeat_start: Option<i32>,
+    pub repeat_end: Option<i32>,
+    pub release_start: Option<i32>,
+}
+
+impl ToneNumber {
+    pub fn tone_number(&self) -> u16 {
+        if self.command == "@@" {
+            self.value + 128
+        } else {
+            self.value
+        }
+    }
+
+    fn to_some_i32(sign: Option<NegativePositive>, value: Option<u16>) -> Option<i32> {
+        value.map(|v| match sign {
+            Some(NegativePositive::Negative) => -(v as i32),
+            Some(NegativePositive::Positive) | None => v as i32,
+        })
+    }
+}
+
+impl PartCommandStruct for ToneNumber {
+    fn to_variant(self) -> PartCommand {
+        PartCommand::ToneNumber(self)
+    }
+
+    fn is_block() -> bool {
+        false
+    }
+
+    fn is_match(command: &str) -> bool {
+        ["@", "@@"].contains(&command)
+    }
+
+    fn parse(working: &mut crate::meta_models::Pass2Working, c: char) -> PartCommandParseState {
+        match c {
+            '0'..='9' => {
+                match working.state {
+                    // tone number
+                    1 => working.jump(2),
+                    // sign is not specified
+                    3 | 5 | 7 => working.next(),
+                    _ => {}
+                }
+
+                working.eat(c);
+            }
+            ',' => {
+                if !(working.state == 2 || working.state == 4 || working.state == 6) {
+                    panic!("ToneNumber: unexpected {c}");
+                }
+
+                working.push();
+                working.next();
+            }
+            '+' | '-' => {
+                if !(working.state == 3 || working.state == 5 || working.state == 7) {
+                    panic!("ToneNumber: unexpected {c}");
+                }
+
+                working.eat(c);
+                working.push();
+                working.next();
+            }
+            _ => {
+                // other command
+                working.push();
+
+                return PartCommandParseState::Parsed;
+            }
+        }
+
+        PartCommandParseState::Parsing
+    }
+}
+
+impl TryFrom<PartTokenStack> for ToneNumber {
+    type Error = Pass2Error;
+
+    fn try_from(mut value: PartTokenStack) -> Result<Self, Self::Error> {
+        let command = try_from_get_value!(value.pop_and_cast(1), command);
+        let tone = try_from_get_value!(value.pop_and_cast::<u16>(2), value);
+
+        let repeat_start_sign = try_from_get_some_value!(value.pop_and_cast(3), repeat_start);
+        let repeat_start = Self::to_some_i32(
+            repeat_start_sign,
+            try_from_get_some_value!(value.pop_and_cast(4), repeat_start),
+        );
+        let repeat_end_sign = try_from_get_some_value!(value.pop_and_cast(5), repeat_end);
+        let repeat_end = Self::to_some_i32(
+            repeat_end_sign,
+            try_from_get_some_value!(value.pop_and_cast(6), repeat_end),
+        );
+        let release_start_sign = try_from_get_some_value!(value.pop_and_cast(7), release_start);
+        let release_start = Self::to_some_i32(
+            release_start_sign,
+            try_from_get_some_value!(value.pop_and_cast(8), release_start),
+        );
+
+        Ok(Self {
+            command,
+            value: tone,
+            repeat_start,
+            repeat_end,
+            release_start,
+        })
+    }
+}
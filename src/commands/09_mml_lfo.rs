@@ -0,0 +1,408 @@
+use crate::{
+    errors::Pass2Error,
+    models::NegativePositive,
+    part_command::{PartCommand, PartCommandParseState, PartCommandStruct, PartTokenStack},
+};
+
+// ===============================================================================
+// §9-1	ソフトウエアLFO設定
+// 	M
+// -------------------------------------------------------------------------------
+// [書式]	M[A/B] 数値1,数値2,数値3,数値4
+// -------------------------------------------------------------------------------
+// [範囲]	数値1	0～255	(ディレイ)
+// 	数値2	0～255	(スピード)
+// 	数値3	-128～+127	(変化量)
+// 	数値4	0～255	(変化回数)
+// -------------------------------------------------------------------------------
+// [音源]	FM / SSG / PCM
+// -------------------------------------------------------------------------------
+// 	ソフトウエアLFOの各値を設定します。
+// 	A,Bを省略した場合は、LFO A を設定します。
+//
+// 	発音後、数値1クロック待ってからLFOがかかり始め、
+// 	数値2クロック毎に数値3ずつ変化し、数値4回変化すると向きが変わります。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SoftwareLfo {
+    pub command: String,
+    pub delay: u8,
+    pub speed: u8,
+    pub depth1: i8,
+    pub depth2: u8,
+}
+
+impl PartCommandStruct for SoftwareLfo {
+    fn to_variant(self) -> PartCommand {
+        match self.command.as_str() {
+            "M" | "MA" => PartCommand::SoftwareLfoA(self),
+            "MB" => PartCommand::SoftwareLfoB(self),
+            _ => {
+                panic!("unexpected command: {}", self.command);
+            }
+        }
+    }
+
+    fn is_block() -> bool {
+        false
+    }
+
+    fn is_match(command: &str) -> bool {
+        ["M", "MA", "MB"].contains(&command)
+    }
+
+    fn parse(working: &mut crate::meta_models::Pass2Working, c: char) -> PartCommandParseState {
+        match c {
+            // M 0,1,2,3
+            ' ' | '\t' if working.state == 1 => {}
+            '0'..='9' => {
+                match working.state {
+                    1 => working.jump(2),
+                    // sign of depth1 is not specified
+                    4 => working.jump(5),
+                    _ => {}
+                }
+
+                working.eat(c);
+            }
+            ',' => {
+                working.push();
+                working.next();
+            }
+            '+' | '-' if working.state == 4 => {
+                working.eat(c);
+                working.push();
+                working.next();
+            }
+            _ => {
+                // other command
+                working.push();
+
+                return PartCommandParseState::Parsed;
+            }
+        }
+
+        PartCommandParseState::Parsing
+    }
+}
+
+impl TryFrom<PartTokenStack> for SoftwareLfo {
+    type Error = Pass2Error;
+
+    fn try_from(mut value: PartTokenStack) -> Result<Self, Self::Error> {
+        let command = try_from_get_value!(value.pop_and_cast(1), command);
+        let delay = try_from_get_value!(value.pop_and_cast(2), delay);
+        let speed = try_from_get_value!(value.pop_and_cast(3), speed);
+
+        let depth1_sign =
+            try_from_get_some_value!(value.pop_and_cast::<NegativePositive>(4), depth1_sign);
+        let depth1 = signed(
+            depth1_sign,
+            try_from_get_value!(value.pop_and_cast::<u8>(5), depth1),
+        );
+
+        let depth2 = try_from_get_value!(value.pop_and_cast(6), depth2);
+
+        Ok(Self {
+            command,
+            delay,
+            speed,
+            depth1,
+            depth2,
+        })
+    }
+}
+
+// ===============================================================================
+// §9-4	LFO波形指定
+// 	MW
+// -------------------------------------------------------------------------------
+// [書式]	MW[A/B] 数値
+// -------------------------------------------------------------------------------
+// [範囲]	0～6
+// -------------------------------------------------------------------------------
+// [音源]	FM / SSG / PCM
+// -------------------------------------------------------------------------------
+// 	ソフトウエアLFOの波形を指定します。
+//
+// 	0	三角波1
+// 	1	のこぎり波
+// 	2	矩形波
+// 	3	ランダム波
+// 	4	三角波2
+// 	5	三角波3
+// 	6	ワンショット
+//
+// 	MX
+// -------------------------------------------------------------------------------
+// [書式]	MX[A/B] 数値
+// -------------------------------------------------------------------------------
+// [範囲]	0～1
+// -------------------------------------------------------------------------------
+// 	ソフトウエアLFOの速度を、0でテンポに依存するノーマル仕様に、
+// 	1でテンポに依存しない拡張仕様にします。
+// 	#LFOSpeed より優先されます。
+//
+// 	MM
+// -------------------------------------------------------------------------------
+// [書式]	MM[A/B] 数値
+// -------------------------------------------------------------------------------
+// [範囲]	0～15
+// -------------------------------------------------------------------------------
+// [音源]	FM
+// -------------------------------------------------------------------------------
+// 	ソフトウエアLFOをかけるスロットを指定します。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LfoOption {
+    pub command: String,
+    pub value: u8,
+}
+
+impl PartCommandStruct for LfoOption {
+    fn to_variant(self) -> PartCommand {
+        match self.command.as_str() {
+            "MW" | "MWA" => PartCommand::LfoWaveformA(self),
+            "MWB" => PartCommand::LfoWaveformB(self),
+            "MX" | "MXA" => PartCommand::LfoSpeedModeA(self),
+            "MXB" => PartCommand::LfoSpeedModeB(self),
+            "MM" | "MMA" => PartCommand::LfoSlotMaskA(self),
+            "MMB" => PartCommand::LfoSlotMaskB(self),
+            _ => {
+                panic!("unexpected command: {}", self.command);
+            }
+        }
+    }
+
+    fn is_block() -> bool {
+        false
+    }
+
+    fn is_match(command: &str) -> bool {
+        ["MW", "MWA", "MWB", "MX", "MXA", "MXB", "MM", "MMA", "MMB"].contains(&command)
+    }
+
+    fn parse(working: &mut crate::meta_models::Pass2Working, c: char) -> PartCommandParseState {
+        match c {
+            ' ' | '\t' if working.state == 1 => {}
+            '0'..='9' => {
+                working.eat(c);
+                working.jump(2);
+            }
+            _ => {
+                // other command
+                working.push();
+
+                return PartCommandParseState::Parsed;
+            }
+        }
+
+        PartCommandParseState::Parsing
+    }
+}
+
+impl TryFrom<PartTokenStack> for LfoOption {
+    type Error = Pass2Error;
+
+    fn try_from(mut value: PartTokenStack) -> Result<Self, Self::Error> {
+        let command = try_from_get_value!(value.pop_and_cast(1), command);
+        let value = try_from_get_value!(value.pop_and_cast(2), value);
+
+        Ok(Self { command, value })
+    }
+}
+
+// ===============================================================================
+// 	MP
+// -------------------------------------------------------------------------------
+// [書式]	MP[A/B] 数値1[,数値2[,数値3]]
+// -------------------------------------------------------------------------------
+// [範囲]	数値1	-128～+127	(深さ)
+// 	数値2	0～255	(ディレイ)
+// 	数値3	0～255	(スピード)
+// -------------------------------------------------------------------------------
+// [音源]	FM / SSG / PCM
+// -------------------------------------------------------------------------------
+// 	ピッチLFOを簡易設定します。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PitchLfo {
+    pub command: String,
+    pub depth: i8,
+    pub delay: Option<u8>,
+    pub speed: Option<u8>,
+}
+
+impl PartCommandStruct for PitchLfo {
+    fn to_variant(self) -> PartCommand {
+        match self.command.as_str() {
+            "MP" | "MPA" => PartCommand::PitchLfoA(self),
+            "MPB" => PartCommand::PitchLfoB(self),
+            _ => {
+                panic!("unexpected command: {}", self.command);
+            }
+        }
+    }
+
+    fn is_block() -> bool {
+        false
+    }
+
+    fn is_match(command: &str) -> bool {
+        ["MP", "MPA", "MPB"].contains(&command)
+    }
+
+    fn parse(working: &mut crate::meta_models::Pass2Working, c: char) -> PartCommandParseState {
+        match c {
+            ' ' | '\t' if working.state == 1 => {}
+            '+' | '-' if working.state == 1 => {
+                // sign of depth
+                working.jump(2);
+                working.eat(c);
+                working.push();
+                working.next();
+            }
+            '0'..='9' => {
+                if working.state == 1 {
+                    working.jump(3);
+                }
+
+                working.eat(c);
+            }
+            ',' => {
+                working.push();
+                working.next();
+            }
+            _ => {
+                // other command
+                working.push();
+
+                return PartCommandParseState::Parsed;
+            }
+        }
+
+        PartCommandParseState::Parsing
+    }
+}
+
+impl TryFrom<PartTokenStack> for PitchLfo {
+    type Error = Pass2Error;
+
+    fn try_from(mut value: PartTokenStack) -> Result<Self, Self::Error> {
+        let command = try_from_get_value!(value.pop_and_cast(1), command);
+        let sign = try_from_get_some_value!(value.pop_and_cast::<NegativePositive>(2), sign);
+        let depth = signed(
+            sign,
+            try_from_get_value!(value.pop_and_cast::<u8>(3), depth),
+        );
+        let delay = try_from_get_some_value!(value.pop_and_cast(4), delay);
+        let speed = try_from_get_some_value!(value.pop_and_cast(5), speed);
+
+        Ok(Self {
+            command,
+            depth,
+            delay,
+            speed,
+        })
+    }
+}
+
+// ===============================================================================
+// 	MD
+// -------------------------------------------------------------------------------
+// [書式]	MD[A/B] 数値1[,数値2[,数値3]]
+// -------------------------------------------------------------------------------
+// [範囲]	数値1	0～255	(スピード)
+// 	数値2	-128～+127	(変化量)
+// 	数値3	0～127	(回数)
+// -------------------------------------------------------------------------------
+// [音源]	FM / SSG / PCM
+// -------------------------------------------------------------------------------
+// 	ソフトウエアLFOの変化量を、数値1クロック毎に数値2ずつ、
+// 	数値3回変化させます。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LfoDepthChange {
+    pub command: String,
+    pub speed: u8,
+    pub depth: Option<i8>,
+    pub count: Option<u8>,
+}
+
+impl PartCommandStruct for LfoDepthChange {
+    fn to_variant(self) -> PartCommand {
+        match self.command.as_str() {
+            "MD" | "MDA" => PartCommand::LfoDepthChangeA(self),
+            "MDB" => PartCommand::LfoDepthChangeB(self),
+            _ => {
+                panic!("unexpected command: {}", self.command);
+            }
+        }
+    }
+
+    fn is_block() -> bool {
+        false
+    }
+
+    fn is_match(command: &str) -> bool {
+        ["MD", "MDA", "MDB"].contains(&command)
+    }
+
+    fn parse(working: &mut crate::meta_models::Pass2Working, c: char) -> PartCommandParseState {
+        match c {
+            ' ' | '\t' if working.state == 1 => {}
+            '0'..='9' => {
+                match working.state {
+                    1 => working.jump(2),
+                    // sign of depth is not specified
+                    3 => working.jump(4),
+                    _ => {}
+                }
+
+                working.eat(c);
+            }
+            ',' => {
+                working.push();
+                working.next();
+            }
+            '+' | '-' if working.state == 3 => {
+                working.eat(c);
+                working.push();
+                working.next();
+            }
+            _ => {
+                // other command
+                working.push();
+
+                return PartCommandParseState::Parsed;
+            }
+        }
+
+        PartCommandParseState::Parsing
+    }
+}
+
+impl TryFrom<PartTokenStack> for LfoDepthChange {
+    type Error = Pass2Error;
+
+    fn try_from(mut value: PartTokenStack) -> Result<Self, Self::Error> {
+        let command = try_from_get_value!(value.pop_and_cast(1), command);
+        let speed = try_from_get_value!(value.pop_and_cast(2), speed);
+        let sign = try_from_get_some_value!(value.pop_and_cast::<NegativePositive>(3), sign);
+        let depth = try_from_get_some_value!(value.pop_and_cast::<u8>(4), depth)
+            .map(|depth| signed(sign, depth));
+        let count = try_from_get_some_value!(value.pop_and_cast(5), count);
+
+        Ok(Self {
+            command,
+            speed,
+            depth,
+            count,
+        })
+    }
+}
+
+fn signed(sign: Option<NegativePositive>, value: u8) -> i8 {
+    let value = value as i16;
+    match sign {
+        Some(NegativePositive::Negative) => -value,
+        _ => value,
+    }
+    .clamp(i8::MIN as i16, i8::MAX as i16) as i8
+}
@@ -5,6 +5,56 @@ use crate::{
     },
 };
 
+// ===============================================================================
+// §10-1	全体ループ
+// 	L
+// -------------------------------------------------------------------------------
+// [書式]	L
+// -------------------------------------------------------------------------------
+// [音源]	FM / SSG / PCM / R選択 / R定義
+// -------------------------------------------------------------------------------
+// 	曲全体のループ開始位置を指定します。
+// 	パートの最後まで演奏すると、L の位置に戻って演奏を続けます。
+// 	L のないパートは、最後まで演奏すると演奏を終了します。
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct SongLoop {
+    pub command: String,
+}
+
+impl PartCommandStruct for SongLoop {
+    fn to_variant(self) -> PartCommand {
+        PartCommand::SongLoop(self)
+    }
+
+    fn is_block() -> bool {
+        false
+    }
+
+    fn is_match(command: &str) -> bool {
+        command == "L"
+    }
+
+    fn parse(working: &mut crate::meta_models::Pass2Working, _c: char) -> PartCommandParseState {
+        // no parameter
+        working.push();
+
+        PartCommandParseState::Parsed
+    }
+}
+
+impl TryFrom<PartTokenStack> for SongLoop {
+    type Error = Pass2Error;
+
+    fn try_from(mut value: PartTokenStack) -> Result<Self, Self::Error> {
+        let command = try_from_get_value!(value.pop_and_cast::<String>(0), command);
+
+        Ok(SongLoop { command })
+    }
+}
+
+// ] without the count when #LoopDefault is not set
+pub const DEFAULT_LOOP_COUNT: u8 = 2;
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct LocalLoop {
     pub begin_command: String,
@@ -13,6 +63,20 @@ pub struct LocalLoop {
     pub body_post: Vec<WrappedPartCommand>,
     pub end_command: String,
     pub count: Option<u8>,
+    // count or #LoopDefault, 0 is an infinite loop
+    pub effective_count: u8,
+}
+
+impl LocalLoop {
+    pub fn apply_loop_default(&mut self, loop_default: u8) {
+        self.effective_count = self.count.unwrap_or(loop_default);
+
+        for command in self.body_pre.iter_mut().chain(self.body_post.iter_mut()) {
+            if let PartCommand::LocalLoop(l) = command.data_mut() {
+                l.apply_loop_default(loop_default);
+            }
+        }
+    }
 }
 
 impl PartCommandStruct for LocalLoop {
@@ -30,32 +94,15 @@ impl PartCommandStruct for LocalLoop {
 
     fn parse(working: &mut crate::meta_models::Pass2Working, c: char) -> PartCommandParseState {
         match c {
-            '0'..='9' => {
-                if working.state < 5 {
-                    panic!("LocalLoop: unexpected {c}");
-                }
-
-                if working.state == 5 {
-                    //
-                }
-
-                working.eat(c);
-                working.jump(6);
-            }
-            ']' => {
-                if working.state != 5 {
-                    panic!("LocalLoop: unexpected {c}");
-                }
-
+            ']' if working.state == 5 => {
                 working.push();
                 working.jump(6);
             }
+            '0'..='9' if working.state == 6 => {
+                working.eat(c);
+            }
             _ => {
-                if working.state != 6 {
-                    panic!("LocalLoop: unexpected {c}");
-                }
-
-                // other command
+                // other command, including ] of the outer loop
                 working.push();
 
                 return PartCommandParseState::Parsed;
@@ -70,21 +117,29 @@ impl TryFrom<PartTokenStack> for LocalLoop {
     type Error = Pass2Error;
 
     fn try_from(mut value: PartTokenStack) -> Result<Self, Self::Error> {
-        println!("TryFrom LocalLoop: {:?}", value);
-
         let begin_command = try_from_get_value!(value.pop_and_cast::<String>(0), begin_command);
-        let body_pre = value.part_command_stack_mut().pop_vec().unwrap_or_default();
+        let separator = try_from_get_some_value!(value.pop_and_cast::<String>(3), separator);
         let end_command = try_from_get_value!(value.pop_and_cast::<String>(5), end_comamnd);
-        let body_post = value.part_command_stack_mut().pop_vec().unwrap_or_default();
+
+        // body_post is pushed after body_pre when : is used
+        let last = value.part_command_stack_mut().pop_vec().unwrap_or_default();
+        let (body_pre, body_post) = match separator {
+            Some(_) => (
+                value.part_command_stack_mut().pop_vec().unwrap_or_default(),
+                last,
+            ),
+            None => (last, vec![]),
+        };
         let count = try_from_get_some_value!(value.pop_and_cast::<u8>(6), count);
 
         Ok(LocalLoop {
             begin_command,
             body_pre,
-            separator: None,
+            separator,
             body_post,
             end_command,
             count,
+            effective_count: count.unwrap_or(DEFAULT_LOOP_COUNT),
         })
     }
 }
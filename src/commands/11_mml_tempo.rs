@@ -0,0 +1,86 @@
+use crate::{
+    errors::Pass2Error,
+    part_command::{PartCommand, PartCommandParseState, PartCommandStruct, PartTokenStack},
+};
+
+// ===============================================================================
+// §11-1	テンポ指定1
+// 	t
+// -------------------------------------------------------------------------------
+// [書式]	t 数値
+// -------------------------------------------------------------------------------
+// [範囲]	18～255
+// -------------------------------------------------------------------------------
+// [音源]	FM / SSG / PCM / R選択 / R定義
+// -------------------------------------------------------------------------------
+// 	テンポを指定します。(->§2-10)
+// 	内部クロック 48 が１分間に何回になるかを指定します。
+//
+// ===============================================================================
+// §11-2	テンポ指定2
+// 	T
+// -------------------------------------------------------------------------------
+// [書式]	T 数値
+// -------------------------------------------------------------------------------
+// [範囲]	0～250
+// -------------------------------------------------------------------------------
+// [音源]	FM / SSG / PCM / R選択 / R定義
+// -------------------------------------------------------------------------------
+// 	TimerB の値を直接指定します。(->§2-10)
+//
+// 	どちらのコマンドも、どのパートで指定しても曲全体のテンポが変化します。
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Tempo {
+    pub command: String,
+    pub value: u8,
+}
+
+impl PartCommandStruct for Tempo {
+    fn to_variant(self) -> PartCommand {
+        match self.command.as_str() {
+            "t" => PartCommand::Tempo1(self),
+            "T" => PartCommand::Tempo2(self),
+            _ => {
+                panic!("unexpected command: {}", self.command);
+            }
+        }
+    }
+
+    fn is_block() -> bool {
+        false
+    }
+
+    fn is_match(command: &str) -> bool {
+        command == "t" || command == "T"
+    }
+
+    fn parse(working: &mut crate::meta_models::Pass2Working, c: char) -> PartCommandParseState {
+        match c {
+            '0'..='9' => {
+                // value, required
+                working.eat(c);
+                working.jump(1);
+            }
+            _ => {
+                // other command
+                working.push();
+
+                return PartCommandParseState::Parsed;
+            }
+        }
+
+        PartCommandParseState::Parsing
+    }
+}
+
+impl TryFrom<PartTokenStack> for Tempo {
+    type Error = Pass2Error;
+
+    fn try_from(mut value: PartTokenStack) -> Result<Self, Self::Error> {
+        let command = try_from_get_value!(value.pop_and_cast(0), command);
+        let value = try_from_get_value!(value.pop_and_cast::<u8>(1), value);
+
+        Ok(Tempo { command, value })
+    }
+}
+
@@ -62,8 +62,6 @@ impl TryFrom<PartTokenStack> for Alpeggio {
     type Error = Pass2Error;
 
     fn try_from(mut value: PartTokenStack) -> Result<Self, Self::Error> {
-        println!("{:?}", value);
-
         let command_begin = try_from_get_value!(value.pop_and_cast(1), command);
         let notes = value.part_command_stack_mut().pop_vec().unwrap_or_default();
         let command_end = try_from_get_value!(value.pop_and_cast(3), command);
@@ -114,7 +112,7 @@ impl PartCommandStruct for Alpeggio {
     fn parse(working: &mut crate::meta_models::Pass2Working, c: char) -> PartCommandParseState {
         match c {
             '%' => {
-                if !vec![4, 6].contains(&working.state) {
+                if ![4, 6].contains(&working.state) {
                     panic!("Alpeggio: unexpected {c}");
                 }
 
@@ -123,11 +121,11 @@ impl PartCommandStruct for Alpeggio {
             }
             '0'..='9' => {
                 // length, required
-                if vec![3, 5, 6, 7, 9].contains(&working.state) {
+                if [3, 5, 6, 7, 9].contains(&working.state) {
                     working.next();
                 }
 
-                if !vec![4, 6, 7, 8, 10].contains(&working.state) {
+                if ![4, 6, 7, 8, 10].contains(&working.state) {
                     panic!("Alpeggio: unexpected {c}");
                 }
 
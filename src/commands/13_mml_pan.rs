@@ -1 +1,105 @@
+use crate::{
+    errors::Pass2Error,
+    models::NegativePositive,
+    part_command::{
+        PartCommand, PartCommandParseState, PartCommandStruct, PartTokenStack, to_some_i8,
+    },
+};
 
+// ===============================================================================
+// §13-1	パンポット指定
+// 	p
+// -------------------------------------------------------------------------------
+// [書式]	p 数値
+// -------------------------------------------------------------------------------
+// [範囲]	0～3 (0=出力なし 1=右 2=左 3=中央)
+// -------------------------------------------------------------------------------
+// ===============================================================================
+// §13-2	拡張パンポット指定
+// 	px
+// -------------------------------------------------------------------------------
+// [書式]	px ±数値1[,数値2]
+// -------------------------------------------------------------------------------
+// [範囲]	数値1	-4～+4
+// 	数値2	0～1 (逆相)
+// -------------------------------------------------------------------------------
+// 	PMD86 / PMDPPZ / PMDPPZE でのみ有効です。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pan {
+    pub command: String,
+    pub value: i8,
+    pub phase: Option<u8>,
+}
+
+impl PartCommandStruct for Pan {
+    fn to_variant(self) -> PartCommand {
+        match self.command.as_str() {
+            "p" => PartCommand::Pan(self),
+            "px" => PartCommand::PanEx(self),
+            _ => panic!("unexpected command: {}", self.command),
+        }
+    }
+
+    fn is_block() -> bool {
+        false
+    }
+
+    fn is_match(command: &str) -> bool {
+        ["p", "px"].contains(&command)
+    }
+
+    fn parse(working: &mut crate::meta_models::Pass2Working, c: char) -> PartCommandParseState {
+        match c {
+            '+' | '-' => {
+                if working.state > 1 {
+                    panic!("Pan: unexpected {c}");
+                }
+
+                working.jump(2);
+
+                working.eat(c);
+                working.push();
+            }
+            '0'..='9' => {
+                if working.state < 3 {
+                    working.jump(3);
+                }
+
+                working.eat(c);
+            }
+            ',' => {
+                if working.state != 3 {
+                    panic!("Pan: unexpected {c}");
+                }
+
+                working.push();
+                working.jump(4);
+            }
+            _ => {
+                // other command
+                working.push();
+
+                return PartCommandParseState::Parsed;
+            }
+        }
+
+        PartCommandParseState::Parsing
+    }
+}
+
+impl TryFrom<PartTokenStack> for Pan {
+    type Error = Pass2Error;
+
+    fn try_from(mut value: PartTokenStack) -> Result<Self, Self::Error> {
+        let command = try_from_get_value!(value.pop_and_cast(1), command);
+        let sign = try_from_get_some_value!(value.pop_and_cast::<NegativePositive>(2), sign);
+        let pan = try_from_get_value!(value.pop_and_cast::<u8>(3), value);
+        let phase = try_from_get_some_value!(value.pop_and_cast::<u8>(4), phase);
+
+        Ok(Self {
+            command,
+            value: to_some_i8(sign, Some(pan)).unwrap(),
+            phase,
+        })
+    }
+}
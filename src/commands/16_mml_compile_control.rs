@@ -0,0 +1,61 @@
+use crate::{
+    errors::Pass2Error,
+    part_command::{PartCommand, PartCommandParseState, PartCommandStruct, PartTokenStack},
+};
+
+// | followed by part symbols limits the commands up to the next | to those
+// parts, as MC does. a bare | ends the limit and is checked as a bar line to
+// fall on the start of a measure. both compile to nothing
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct BarLine {
+    pub command: String,
+    pub parts: Option<String>,
+}
+
+impl BarLine {
+    // false while the commands are limited to other parts
+    pub fn is_active(&self, symbol: char) -> bool {
+        self.parts
+            .as_ref()
+            .is_none_or(|parts| parts.contains(symbol))
+    }
+}
+
+impl PartCommandStruct for BarLine {
+    fn to_variant(self) -> PartCommand {
+        PartCommand::BarLine(self)
+    }
+
+    fn is_block() -> bool {
+        false
+    }
+
+    fn is_match(command: &str) -> bool {
+        command == "|"
+    }
+
+    fn parse(working: &mut crate::meta_models::Pass2Working, c: char) -> PartCommandParseState {
+        if c.is_ascii_alphabetic() {
+            // part symbols
+            working.eat(c);
+
+            return PartCommandParseState::Parsing;
+        }
+
+        // other command
+        working.push();
+
+        PartCommandParseState::Parsed
+    }
+}
+
+impl TryFrom<PartTokenStack> for BarLine {
+    type Error = Pass2Error;
+
+    fn try_from(mut value: PartTokenStack) -> Result<Self, Self::Error> {
+        let command = try_from_get_value!(value.pop_and_cast::<String>(0), command);
+        let parts = try_from_get_some_value!(value.pop_and_cast::<String>(1), parts);
+
+        Ok(BarLine { command, parts })
+    }
+}
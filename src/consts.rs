@@ -1,2 +1,4 @@
+#![allow(dead_code)]
+
 pub const BASED_MC_VERSION: &str = "4.8s";
 pub const PROGRAM_BUFFER_LENGTH: usize = 26; // prgbuf_length [bytes]
@@ -0,0 +1,88 @@
+use crate::{
+    errors::SemanticError,
+    meta_models::Code,
+    models::{ExtendPartSymbol, MeasureType, PartSymbol},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: Code,
+    pub part: Option<PartSymbol>,
+    pub extend_part: Option<ExtendPartSymbol>,
+    // filled in by Timeline::locate
+    pub measure: Option<MeasureType>,
+    pub error: SemanticError,
+}
+
+impl Diagnostic {
+    pub fn warning(code: &Code, part: Option<PartSymbol>, error: SemanticError) -> Self {
+        Self {
+            severity: Severity::Warning,
+            code: code.clone(),
+            part,
+            extend_part: None,
+            measure: None,
+            error,
+        }
+    }
+
+    pub fn error(code: &Code, part: Option<PartSymbol>, error: SemanticError) -> Self {
+        Self {
+            severity: Severity::Error,
+            code: code.clone(),
+            part,
+            extend_part: None,
+            measure: None,
+            error,
+        }
+    }
+
+    // found in a part declared by #FM3Extend or #PPZExtend
+    pub fn in_extend_part(mut self, part: ExtendPartSymbol) -> Self {
+        self.extend_part = Some(part);
+        self
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+}
+
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let severity = match self.severity {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+
+        write!(
+            f,
+            "{}:{}:{}: {}: ",
+            self.code.file_name,
+            self.code.lines + 1,
+            self.code.chars + 1,
+            severity
+        )?;
+
+        let part = match (&self.part, &self.extend_part) {
+            (Some(part), _) => Some(format!("{:?}", part)),
+            (None, Some(part)) => Some(format!("{:?}", part)),
+            (None, None) => None,
+        };
+
+        match (part, self.measure) {
+            (Some(part), Some(measure)) => write!(f, "part {}, measure {}: ", part, measure)?,
+            (Some(part), None) => write!(f, "part {}: ", part)?,
+            _ => {}
+        }
+
+        write!(f, "{}", self.error)
+    }
+}
@@ -0,0 +1,355 @@
+use crate::{
+    commands::commands_envelope::SsgPcmSoftwareEnvelope,
+    events::Clock,
+    meta_models::Pass2Result,
+    models::{EnvelopeSpeedMacro, ExtendNormalOption},
+    timeline::{Seconds, clock_seconds},
+};
+
+// with #EnvelopeSpeed Extend (EX1) the envelope runs on the fixed interrupt
+// of the driver instead of the internal clock, about 56 times a second
+pub const EXTEND_TICK: Seconds = 1.0 / 56.0;
+
+const MAX_LEVEL: i8 = 15;
+
+// the last #EnvelopeSpeed, Normal when not given
+pub fn envelope_speed(pass2: &Pass2Result) -> ExtendNormalOption {
+    pass2
+        .find_macros("EnvelopeSpeed")
+        .last()
+        .map(|m| EnvelopeSpeedMacro::from((*m).clone()).value)
+        .unwrap_or(ExtendNormalOption::Normal)
+}
+
+// envelope ticks while `clocks` are played at `timer_b`.
+// Normal ticks once per internal clock, so the curve follows the tempo
+pub fn envelope_ticks(speed: &ExtendNormalOption, clocks: Clock, timer_b: u8) -> u32 {
+    match speed {
+        ExtendNormalOption::Normal => clocks,
+        ExtendNormalOption::Extend => {
+            (clocks as Seconds * clock_seconds(timer_b) / EXTEND_TICK).round() as u32
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SoftwareEnvelope {
+    // E AL,DD,SR,RR
+    // DD is added to the volume AL ticks after key on, then the volume goes
+    // down by 1 every SR ticks, and every RR ticks after key off.
+    // 0 of SR keeps the volume, 0 of RR mutes at key off
+    Old {
+        al: u8,
+        dd: i8,
+        sr: u8,
+        rr: u8,
+    },
+    // E AR,DR,SR,RR,SL[,AL]
+    // the level rises from AL to 15 at AR, falls to 15-SL at DR, then to 0
+    // at SR, and at RR after key off
+    New {
+        ar: u8,
+        dr: u8,
+        sr: u8,
+        rr: u8,
+        sl: u8,
+        al: u8,
+    },
+}
+
+impl From<&SsgPcmSoftwareEnvelope> for SoftwareEnvelope {
+    fn from(e: &SsgPcmSoftwareEnvelope) -> Self {
+        match e.value5 {
+            None => SoftwareEnvelope::Old {
+                al: e.value1,
+                dd: e.value2,
+                sr: e.value3,
+                rr: e.value4,
+            },
+            Some(sl) => SoftwareEnvelope::New {
+                ar: e.value1,
+                dr: e.value2.max(0) as u8,
+                sr: e.value3,
+                rr: e.value4,
+                sl,
+                al: e.value6.unwrap_or(0),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EnvelopePhase {
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+}
+
+// the envelope of one note, key on when created
+#[derive(Debug, Clone)]
+pub struct EnvelopeGenerator {
+    envelope: SoftwareEnvelope,
+    phase: EnvelopePhase,
+    // Old: offset added to the volume, -15..+15
+    // New: envelope level, 0..15
+    level: i8,
+    counter: u8,
+}
+
+impl EnvelopeGenerator {
+    pub fn new(envelope: SoftwareEnvelope) -> Self {
+        let mut generator = Self {
+            envelope,
+            phase: EnvelopePhase::Attack,
+            level: 0,
+            counter: 0,
+        };
+
+        match envelope {
+            SoftwareEnvelope::Old { al: 0, dd, .. } => {
+                generator.level = dd.clamp(-MAX_LEVEL, MAX_LEVEL);
+                generator.phase = EnvelopePhase::Sustain;
+            }
+            SoftwareEnvelope::Old { .. } => {}
+            SoftwareEnvelope::New { al, .. } => {
+                generator.level = (al as i8).min(MAX_LEVEL);
+                generator.settle();
+            }
+        }
+
+        generator
+    }
+
+    pub fn phase(&self) -> EnvelopePhase {
+        self.phase
+    }
+
+    pub fn key_off(&mut self) {
+        self.phase = EnvelopePhase::Release;
+        self.counter = 0;
+
+        if let SoftwareEnvelope::Old { rr: 0, .. } = self.envelope {
+            self.level = -MAX_LEVEL;
+        }
+    }
+
+    pub fn tick(&mut self) {
+        match self.envelope {
+            SoftwareEnvelope::Old { al, dd, sr, rr } => match self.phase {
+                EnvelopePhase::Attack => {
+                    if self.step(al) {
+                        self.level = dd.clamp(-MAX_LEVEL, MAX_LEVEL);
+                        self.phase = EnvelopePhase::Sustain;
+                    }
+                }
+                EnvelopePhase::Decay | EnvelopePhase::Sustain => {
+                    if self.step(sr) {
+                        self.level = (self.level - 1).max(-MAX_LEVEL);
+                    }
+                }
+                EnvelopePhase::Release => {
+                    if self.step(rr) {
+                        self.level = (self.level - 1).max(-MAX_LEVEL);
+                    }
+                }
+            },
+            SoftwareEnvelope::New { ar, dr, sr, rr, .. } => {
+                match self.phase {
+                    EnvelopePhase::Attack => {
+                        if self.step(rate_period(ar)) {
+                            self.level += 1;
+                        }
+                    }
+                    EnvelopePhase::Decay => {
+                        if self.step(rate_period(dr)) {
+                            self.level -= 1;
+                        }
+                    }
+                    EnvelopePhase::Sustain => {
+                        if self.step(rate_period(sr)) {
+                            self.level = (self.level - 1).max(0);
+                        }
+                    }
+                    EnvelopePhase::Release => {
+                        // RR is 4 bits, as on FM
+                        if self.step(rate_period(rr.min(15) * 2 + 1)) {
+                            self.level = (self.level - 1).max(0);
+                        }
+                    }
+                }
+                self.settle();
+            }
+        }
+    }
+
+    // `volume` of v0..15 through the envelope
+    pub fn volume(&self, volume: u8) -> u8 {
+        match self.envelope {
+            SoftwareEnvelope::Old { .. } => {
+                (volume as i16 + self.level as i16).clamp(0, MAX_LEVEL as i16) as u8
+            }
+            SoftwareEnvelope::New { .. } => {
+                (volume as u16 * self.level as u16 / MAX_LEVEL as u16) as u8
+            }
+        }
+    }
+
+    // counts a tick, true every `period` ticks. 0 never steps
+    fn step(&mut self, period: u8) -> bool {
+        if period == 0 {
+            return false;
+        }
+
+        self.counter += 1;
+        if self.counter < period {
+            return false;
+        }
+
+        self.counter = 0;
+        true
+    }
+
+    // moves on to decay and sustain once the level reaches them
+    fn settle(&mut self) {
+        let SoftwareEnvelope::New { sl, .. } = self.envelope else {
+            return;
+        };
+
+        if self.phase == EnvelopePhase::Attack && self.level >= MAX_LEVEL {
+            self.level = MAX_LEVEL;
+            self.phase = EnvelopePhase::Decay;
+            self.counter = 0;
+        }
+
+        if self.phase == EnvelopePhase::Decay && self.level <= MAX_LEVEL - sl.min(15) as i8 {
+            self.phase = EnvelopePhase::Sustain;
+            self.counter = 0;
+        }
+    }
+}
+
+// ticks per level of the rates of the new form, 31 moves every tick
+fn rate_period(rate: u8) -> u8 {
+    match rate {
+        0 => 0,
+        _ => 32 - rate.min(31),
+    }
+}
+
+// volume of each tick of a note of `ticks` keyed off at `gate`,
+// the ticks after the gate are the release
+pub fn envelope_curve(envelope: SoftwareEnvelope, volume: u8, gate: u32, ticks: u32) -> Vec<u8> {
+    let mut generator = EnvelopeGenerator::new(envelope);
+
+    (0..ticks)
+        .map(|tick| {
+            if tick == gate {
+                generator.key_off();
+            }
+
+            let value = generator.volume(volume);
+            generator.tick();
+            value
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::part_command::PartCommand;
+
+    use super::*;
+
+    #[test]
+    fn test_envelope_old() {
+        let envelope = SoftwareEnvelope::Old {
+            al: 2,
+            dd: -2,
+            sr: 1,
+            rr: 1,
+        };
+        assert_eq!(
+            vec![10, 10, 8, 7, 6, 5, 4, 3],
+            envelope_curve(envelope, 10, 5, 8)
+        );
+
+        // DD at key on, sustained, muted at key off
+        let envelope = SoftwareEnvelope::Old {
+            al: 0,
+            dd: 1,
+            sr: 0,
+            rr: 0,
+        };
+        assert_eq!(vec![11, 11, 0, 0], envelope_curve(envelope, 10, 2, 4));
+    }
+
+    #[test]
+    fn test_envelope_new() {
+        let envelope = SoftwareEnvelope::New {
+            ar: 30,
+            dr: 31,
+            sr: 0,
+            rr: 15,
+            sl: 2,
+            al: 12,
+        };
+        assert_eq!(
+            vec![12, 12, 13, 13, 14, 14, 15, 14, 13, 13, 13, 12],
+            envelope_curve(envelope, 15, 10, 12)
+        );
+
+        let mut generator = EnvelopeGenerator::new(envelope);
+        assert_eq!(EnvelopePhase::Attack, generator.phase());
+        (0..7).for_each(|_| generator.tick());
+        assert_eq!(EnvelopePhase::Decay, generator.phase());
+        generator.tick();
+        assert_eq!(EnvelopePhase::Sustain, generator.phase());
+        generator.key_off();
+        assert_eq!(EnvelopePhase::Release, generator.phase());
+    }
+
+    #[test]
+    fn test_envelope_from_command() {
+        let mml = "G E2,-2,1,1 c\nH E31,18,4,6,2,1 c";
+        let pass2 = crate::parse("", mml).unwrap();
+        let envelopes = pass2
+            .parts
+            .iter()
+            .flat_map(|(_, commands)| commands.iter())
+            .filter_map(|c| match c.data() {
+                PartCommand::SsgPcmSoftwareEnvelope(e) => Some(SoftwareEnvelope::from(e)),
+                _ => None,
+            })
+            .collect::<Vec<SoftwareEnvelope>>();
+
+        assert_eq!(
+            vec![
+                SoftwareEnvelope::Old {
+                    al: 2,
+                    dd: -2,
+                    sr: 1,
+                    rr: 1
+                },
+                SoftwareEnvelope::New {
+                    ar: 31,
+                    dr: 18,
+                    sr: 4,
+                    rr: 6,
+                    sl: 2,
+                    al: 1
+                },
+            ],
+            envelopes
+        );
+        assert_eq!(ExtendNormalOption::Normal, envelope_speed(&pass2));
+    }
+
+    #[test]
+    fn test_envelope_ticks() {
+        assert_eq!(48, envelope_ticks(&ExtendNormalOption::Normal, 48, 200));
+        // 48 clocks of Timer-B 200 are 0.775 seconds
+        assert_eq!(43, envelope_ticks(&ExtendNormalOption::Extend, 48, 200));
+        assert_eq!(87, envelope_ticks(&ExtendNormalOption::Extend, 96, 200));
+    }
+}
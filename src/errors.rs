@@ -1,6 +1,10 @@
 use thiserror::Error;
 
-use crate::meta_models::{CharacterNumber, LineNumber};
+use crate::{
+    meta_models::{CharacterNumber, LineNumber},
+    models::{Chip, ExtendPartSymbol, PartSymbol},
+    target::TargetDriver,
+};
 
 #[derive(Error, Debug)]
 pub enum Pass1Error {
@@ -12,4 +16,56 @@ pub enum Pass1Error {
 pub enum Pass2Error {
     #[error("")]
     ParseError(LineNumber, CharacterNumber),
-}
\ No newline at end of file
+}
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum SemanticError {
+    #[error("{0} is not available on {1:?} parts")]
+    UnsupportedCommand(String, Chip),
+    #[error("{0} behaves differently on {1:?} parts")]
+    DifferentBehaviour(String, Chip),
+    #[error("part {0:?} is not available on {1}")]
+    UnavailablePart(PartSymbol, TargetDriver),
+    #[error("part {0:?} is not declared by #FM3Extend or #PPZExtend")]
+    UndeclaredExtendPart(ExtendPartSymbol),
+    #[error("part {0:?} is not available on {1}")]
+    UnavailableExtendPart(ExtendPartSymbol, TargetDriver),
+    #[error("{0} is not available on {1}")]
+    UnsupportedOnTarget(String, TargetDriver),
+    #[error("{0} is ignored on {1}")]
+    IgnoredOnTarget(String, TargetDriver),
+    #[error("{0} value {1} is out of range ({2}..={3})")]
+    OutOfRange(String, i32, i32, i32),
+    #[error("@{0} requires PPSDRV")]
+    RequiresPpsdrv(u16),
+    #[error("invalid option /{0}")]
+    InvalidOption(String),
+    #[error("length {0} is not a divisor of the whole note length {1}")]
+    NotDivisor(u8, u8),
+    #[error("dotted length of {0} clocks is not an integral clock count")]
+    IndivisibleDots(u32),
+    #[error("length of {0} clocks exceeds 255 steps")]
+    TooLong(u32),
+    #[error("{0} has no note or rest to modify")]
+    NothingToModify(String),
+    #[error("{0} makes the length {1} clocks")]
+    NonPositiveLength(String, i64),
+    #[error("{0} cuts a random length, {1} clocks are cut instead")]
+    RandomCut(String, u32),
+    #[error("& connects notes of different pitches, played as &&")]
+    TieDifferentPitch,
+    #[error("loop count 0 repeats forever")]
+    InfiniteLoop,
+    #[error("{0} has no matching bracket")]
+    UnmatchedLoop(String),
+    #[error(": is used outside of a loop")]
+    StrayLoopSeparator,
+    #[error(": is used twice in a loop")]
+    DuplicateLoopSeparator,
+    #[error("loops are nested deeper than {0}")]
+    LoopNestTooDeep(u8),
+    #[error("loop of {0} clocks differs from part {1:?} by {2:+} clocks")]
+    LoopOutOfSync(u32, PartSymbol, i64),
+    #[error("| is {0} clocks past the start of the measure")]
+    MisplacedBarLine(u32),
+}
@@ -0,0 +1,86 @@
+use crate::{
+    meta_models::Code, models::PartSymbol, part_command::PartCommand, pitch::Pitch,
+    portamento::ResolvedPortamento,
+};
+
+pub type Clock = u32;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EventKind {
+    // c d e f g a b x, portamento and arpeggio
+    Note {
+        command: PartCommand,
+        clocks: Clock,
+        pitch: Option<Pitch>,
+        // sounding length before the key-off
+        gate: Option<Clock>,
+        // connected to the next note without key-off (&&)
+        legato: bool,
+        // V when the note is played, after the volume down, see VolumeResolver
+        volume: Option<u8>,
+        // { } resolved by the length and pitch passes
+        portamento: Option<ResolvedPortamento>,
+    },
+    // r
+    Rest {
+        command: PartCommand,
+        clocks: Clock,
+    },
+    // [ ... : ... ]
+    Loop {
+        count: Option<u8>,
+        body_pre: Vec<Event>,
+        body_post: Vec<Event>,
+    },
+    // commands without length
+    Command(PartCommand),
+    // V level change made by {{ }} at the end of each cycle
+    VolumeShift(i8),
+}
+
+impl EventKind {
+    pub fn with_clocks(&self, clocks: Clock) -> Self {
+        let mut kind = self.clone();
+        if let EventKind::Note { clocks: c, .. } | EventKind::Rest { clocks: c, .. } = &mut kind {
+            *c = clocks;
+        }
+
+        kind
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Event {
+    pub code: Code,
+    pub kind: EventKind,
+    // generated from one command, by splitting a note longer than 255 steps
+    // or by expanding {{ }}
+    pub split: bool,
+}
+
+impl Event {
+    pub fn new(code: &Code, kind: EventKind) -> Self {
+        Self {
+            code: code.clone(),
+            kind,
+            split: false,
+        }
+    }
+
+    pub fn split(code: &Code, kind: EventKind) -> Self {
+        Self {
+            code: code.clone(),
+            kind,
+            split: true,
+        }
+    }
+
+    pub fn clocks(&self) -> Option<Clock> {
+        match &self.kind {
+            EventKind::Note { clocks, .. } | EventKind::Rest { clocks, .. } => Some(*clocks),
+            _ => None,
+        }
+    }
+}
+
+pub type PartEvents = (PartSymbol, Vec<Event>);
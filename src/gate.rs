@@ -0,0 +1,290 @@
+use crate::{
+    commands::commands_mml::{Quantize1, Quantize2},
+    diagnostics::Diagnostic,
+    errors::SemanticError,
+    events::{Clock, Event, EventKind, PartEvents},
+    length::{LengthResolver, LengthState},
+    meta_models::{Code, Pass2Result},
+    models::{DivisorClock, PartSymbol},
+    options::CompileOptions,
+    part_command::PartCommand,
+};
+
+pub const MAX_QUANTIZE: u8 = 8;
+
+// compile time state of q and Q, follows the MML text order
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GateState {
+    pub zenlen: u8,
+    // Q, sounding ratio numerator / denominator
+    pub numerator: Clock,
+    pub denominator: Clock,
+    // q, clocks cut from the end of the note
+    pub cut: Clock,
+    // q ,数値3, guaranteed sounding length
+    pub minimum: Option<Clock>,
+}
+
+impl GateState {
+    pub fn new(zenlen: u8) -> Self {
+        Self {
+            zenlen,
+            numerator: MAX_QUANTIZE as Clock,
+            denominator: MAX_QUANTIZE as Clock,
+            cut: 0,
+            minimum: None,
+        }
+    }
+
+    pub fn apply_quantize1(&mut self, q: &Quantize1) -> Result<(), SemanticError> {
+        match q.divisor {
+            // Q%数値
+            Some(DivisorClock::Clock(_)) => {
+                self.numerator = q.value as Clock;
+                self.denominator = 256;
+            }
+            _ => {
+                if q.value > MAX_QUANTIZE {
+                    return Err(SemanticError::OutOfRange(
+                        q.command.clone(),
+                        q.value as i32,
+                        0,
+                        MAX_QUANTIZE as i32,
+                    ));
+                }
+
+                self.numerator = q.value as Clock;
+                self.denominator = MAX_QUANTIZE as Clock;
+            }
+        }
+
+        Ok(())
+    }
+
+    // q 数値1-数値2 makes the driver pick the cut randomly, the timeline
+    // can't follow it and keeps 数値1, reported as a warning
+    pub fn apply_quantize2(
+        &mut self,
+        q: &Quantize2,
+    ) -> Result<Option<SemanticError>, SemanticError> {
+        self.cut = self.to_clocks(q, q.value1, q.value1_dots)?.unwrap_or(0);
+        self.minimum = self.to_clocks(q, q.value3, q.value3_dots)?;
+
+        let random = self.to_clocks(q, q.value2, 0)?;
+        Ok(random.map(|_| SemanticError::RandomCut(q.command.clone(), self.cut)))
+    }
+
+    fn to_clocks(
+        &self,
+        q: &Quantize2,
+        value: Option<u8>,
+        dots: u8,
+    ) -> Result<Option<Clock>, SemanticError> {
+        let Some(value) = value else {
+            return Ok(None);
+        };
+
+        if q.by_length {
+            LengthState::new(self.zenlen)
+                .resolve(Some(&DivisorClock::Divisor(value)), dots)
+                .map(Some)
+        } else {
+            Ok(Some(value as Clock))
+        }
+    }
+
+    // sounding length of a note, the rest of the length is after the key-off
+    pub fn gate(&self, clocks: Clock, tied: bool) -> Clock {
+        // no key-off before & and &&
+        if tied || clocks == 0 {
+            return clocks;
+        }
+
+        let mut gate = (clocks * self.numerator / self.denominator).saturating_sub(self.cut);
+        if let Some(minimum) = self.minimum {
+            gate = gate.max(minimum);
+        }
+
+        gate.clamp(1, clocks)
+    }
+}
+
+pub struct GateResolver<'a> {
+    pass2: &'a Pass2Result,
+    options: &'a CompileOptions,
+}
+
+impl<'a> GateResolver<'a> {
+    pub fn new(pass2: &'a Pass2Result, options: &'a CompileOptions) -> Self {
+        Self { pass2, options }
+    }
+
+    pub fn resolve(&self, parts: &mut [PartEvents]) -> Vec<Diagnostic> {
+        let zenlen = LengthResolver::new(self.pass2, self.options).zenlen();
+        let mut states: Vec<(PartSymbol, GateState)> = vec![];
+        let mut diagnostics = vec![];
+
+        for (part, events) in parts.iter_mut() {
+            // a part written over several lines continues its state
+            let index = match states.iter().position(|(s, _)| s == part) {
+                Some(index) => index,
+                None => {
+                    states.push((part.clone(), GateState::new(zenlen)));
+                    states.len() - 1
+                }
+            };
+
+            Self::resolve_events(part, events, &mut states[index].1, &mut diagnostics);
+        }
+
+        diagnostics
+    }
+
+    fn resolve_events(
+        part: &PartSymbol,
+        events: &mut [Event],
+        state: &mut GateState,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) {
+        for i in 0..events.len() {
+            let tied = events.get(i + 1).is_some_and(Self::is_tie);
+            let code = events[i].code.clone();
+
+            match &mut events[i].kind {
+                EventKind::Note { clocks, gate, .. } => {
+                    *gate = Some(state.gate(*clocks, tied));
+                }
+                EventKind::Loop {
+                    body_pre,
+                    body_post,
+                    ..
+                } => {
+                    Self::resolve_events(part, body_pre, state, diagnostics);
+                    Self::resolve_events(part, body_post, state, diagnostics);
+                }
+                EventKind::Command(command) => {
+                    let result = match command {
+                        PartCommand::Quantize1(q) => state.apply_quantize1(q).map(|_| None),
+                        PartCommand::Quantize2(q) => state.apply_quantize2(q),
+                        PartCommand::WholeLength(c) if c.value > 0 => {
+                            state.zenlen = c.value;
+                            Ok(None)
+                        }
+                        _ => Ok(None),
+                    };
+
+                    match result {
+                        Ok(Some(w)) => {
+                            diagnostics.push(Diagnostic::warning(&code, Some(part.clone()), w))
+                        }
+                        Ok(None) => {}
+                        Err(e) => Self::report(part, &code, e, diagnostics),
+                    }
+                }
+                EventKind::Rest { .. } | EventKind::VolumeShift(_) => {}
+            }
+        }
+    }
+
+    fn is_tie(event: &Event) -> bool {
+        matches!(
+            event.kind,
+            EventKind::Command(PartCommand::Tie(_) | PartCommand::Slur(_))
+        )
+    }
+
+    fn report(
+        part: &PartSymbol,
+        code: &Code,
+        error: SemanticError,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) {
+        diagnostics.push(Diagnostic::error(code, Some(part.clone()), error));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::diagnostics::Severity;
+
+    use super::*;
+
+    fn mml_gates(mml: &str) -> (Vec<Clock>, Vec<Diagnostic>) {
+        let pass2 = crate::parse("", mml).unwrap();
+        resolve(&pass2)
+    }
+
+    fn resolve(pass2: &Pass2Result) -> (Vec<Clock>, Vec<Diagnostic>) {
+        let options = CompileOptions::default();
+        let (mut parts, _) = LengthResolver::new(pass2, &options).resolve();
+        let diagnostics = GateResolver::new(pass2, &options).resolve(&mut parts);
+
+        let gates = parts[0]
+            .1
+            .iter()
+            .filter_map(|e| match &e.kind {
+                EventKind::Note { gate, .. } => *gate,
+                _ => None,
+            })
+            .collect();
+        (gates, diagnostics)
+    }
+
+    #[test]
+    fn test_gate() {
+        let mut state = GateState::new(96);
+        assert_eq!(24, state.gate(24, false));
+
+        state.numerator = 6;
+        assert_eq!(18, state.gate(24, false));
+        assert_eq!(24, state.gate(24, true));
+
+        state.cut = 2;
+        assert_eq!(16, state.gate(24, false));
+        assert_eq!(1, state.gate(2, false));
+
+        state.minimum = Some(20);
+        assert_eq!(20, state.gate(24, false));
+        assert_eq!(12, state.gate(12, false));
+    }
+
+    #[test]
+    fn test_resolve_quantize() {
+        let (gates, diagnostics) = mml_gates("A c Q6 c Q%64 c q2 c ql16.,l8 Q8 c Q9 c\n");
+
+        assert_eq!(vec![24, 18, 6, 4, 15, 15], gates);
+        assert_eq!(1, diagnostics.len());
+        assert_eq!(
+            SemanticError::OutOfRange("Q".to_string(), 9, 0, 8),
+            diagnostics[0].error
+        );
+
+        // the minimum of ,l8 is kept
+        let (gates, _) = mml_gates("A ql8,l16 c16 c\n");
+        assert_eq!(vec![6, 12], gates);
+    }
+
+    #[test]
+    fn test_resolve_quantize_random() {
+        let (gates, diagnostics) = mml_gates("A q2-4 c ql32-l16 c\n");
+
+        assert_eq!(vec![22, 21], gates);
+        assert_eq!(2, diagnostics.len());
+        assert_eq!(Severity::Warning, diagnostics[0].severity);
+        assert_eq!(
+            SemanticError::RandomCut("q".to_string(), 2),
+            diagnostics[0].error
+        );
+        assert_eq!(
+            SemanticError::RandomCut("q".to_string(), 3),
+            diagnostics[1].error
+        );
+    }
+
+    #[test]
+    fn test_resolve_quantize_tie() {
+        let (gates, _) = mml_gates("A q4 c&c&&c\n");
+
+        assert_eq!(vec![24, 24, 20], gates);
+    }
+}
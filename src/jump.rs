@@ -0,0 +1,158 @@
+use crate::{
+    commands::commands_mml::NoteR,
+    events::{Event, EventKind, PartEvents},
+    measure::MeasureCounter,
+    meta_models::Pass2Result,
+    models::{JumpMacro, MeasureType, PartSymbol},
+    options::CompileOptions,
+    part_command::{PartCommand, PartCommandStruct},
+};
+
+// #Jump only takes effect when MC plays the song with /P or /S,
+// the .M file is not affected
+pub fn jump_measure(pass2: &Pass2Result, options: &CompileOptions) -> Option<MeasureType> {
+    if !(options.play || options.play_only) {
+        return None;
+    }
+
+    pass2
+        .find_macros("Jump")
+        .last()
+        .map(|m| JumpMacro::from((*m).clone()).value)
+        .filter(|measure| *measure > 1)
+}
+
+// drops the notes and rests before `measure`. the commands before it are kept
+// at the head, so tone, volume, octave and tempo are as if played from the top.
+// `parts` are flat, see LoopUnroller
+pub fn skip_to_measure(parts: &[PartEvents], zenlen: u8, measure: MeasureType) -> Vec<PartEvents> {
+    let mut counters: Vec<(PartSymbol, MeasureCounter)> = vec![];
+
+    parts
+        .iter()
+        .map(|(part, events)| {
+            // a part written over several lines continues its measure
+            let index = match counters.iter().position(|(p, _)| p == part) {
+                Some(index) => index,
+                None => {
+                    counters.push((part.clone(), MeasureCounter::new(zenlen)));
+                    counters.len() - 1
+                }
+            };
+            let counter = &mut counters[index].1;
+
+            let mut skipped = vec![];
+            for event in events {
+                if counter.position().measure >= measure {
+                    skipped.push(event.clone());
+                    continue;
+                }
+
+                match &event.kind {
+                    EventKind::Note { clocks, .. } | EventKind::Rest { clocks, .. } => {
+                        counter.advance(*clocks);
+
+                        // the part of a note sounding across the jump is played as a rest
+                        let position = counter.position();
+                        if position.measure >= measure {
+                            let clocks = (position.measure - measure) as u32 * counter.length()
+                                + position.clock;
+                            if clocks > 0 {
+                                skipped.push(Event::split(&event.code, rest(clocks)));
+                            }
+                        }
+                    }
+                    EventKind::Command(PartCommand::WholeLength(c)) => {
+                        counter.set_zenlen(c.value);
+                        skipped.push(event.clone());
+                    }
+                    EventKind::Command(
+                        PartCommand::Tie(_) | PartCommand::Slur(_) | PartCommand::BarLine(_),
+                    ) => {}
+                    _ => skipped.push(event.clone()),
+                }
+            }
+
+            (part.clone(), skipped)
+        })
+        .collect()
+}
+
+fn rest(clocks: u32) -> EventKind {
+    let command = NoteR {
+        command: "r".to_string(),
+        length: None,
+        dots: 0,
+    };
+
+    EventKind::Rest {
+        command: command.to_variant(),
+        clocks,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{events::Clock, measure::Position, timeline::Timeline};
+
+    use super::*;
+
+    fn timeline(mml: &str, options: &CompileOptions) -> Timeline {
+        let pass2 = crate::parse("", mml).unwrap();
+
+        Timeline::new(&pass2, options).0
+    }
+
+    #[test]
+    fn test_jump() {
+        let mml = "#Jump 3\nA o4 c1 o5 @2 c1 t120 d2 e2 f1\nB r1 r2 c1 d2";
+        let options = CompileOptions {
+            play: true,
+            ..Default::default()
+        };
+
+        // only when playing
+        assert_eq!(384, timeline(mml, &CompileOptions::default()).clocks());
+        let timeline = timeline(mml, &options);
+        assert_eq!(192, timeline.clocks());
+        assert_eq!(220, timeline.timer_b_at(0));
+
+        let a = &timeline.parts[0].events;
+        assert!(
+            a[..4]
+                .iter()
+                .all(|e| e.clock == 0 && e.event.clocks().is_none())
+        );
+        assert!(matches!(
+            a[4].event.kind,
+            EventKind::Note {
+                pitch: Some(74),
+                ..
+            }
+        ));
+        assert_eq!(
+            Position {
+                measure: 3,
+                clock: 0
+            },
+            a[4].position
+        );
+
+        // the rest of c1 across the jump
+        let b = &timeline.parts[1].events;
+        assert_eq!(
+            vec![(0, Some(48)), (48, Some(48))],
+            b.iter()
+                .map(|e| (e.clock, e.event.clocks()))
+                .collect::<Vec<(Clock, Option<Clock>)>>()
+        );
+        assert!(matches!(b[0].event.kind, EventKind::Rest { .. }));
+        assert_eq!(
+            Position {
+                measure: 3,
+                clock: 48
+            },
+            b[1].position
+        );
+    }
+}
@@ -0,0 +1,578 @@
+use crate::{
+    alpeggio,
+    commands::commands_mml::{Slur, Tie},
+    diagnostics::Diagnostic,
+    errors::SemanticError,
+    events::{Clock, Event, EventKind, PartEvents},
+    meta_models::Pass2Result,
+    models::{DivisorClock, PartSymbol, ZenLenMacro},
+    options::CompileOptions,
+    part_command::{PartCommand, PartCommandStruct, WrappedPartCommand, count_dots},
+    portamento,
+};
+
+pub const DEFAULT_ZENLEN: u8 = 96;
+pub const DEFAULT_LENGTH: u8 = 4;
+pub const MAX_STEPS: Clock = 255;
+
+// compile time state of lengths, follows the MML text order
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LengthState {
+    pub zenlen: u8,
+    pub default_length: DivisorClock<u8>,
+    pub default_dots: u8,
+}
+
+impl LengthState {
+    pub fn new(zenlen: u8) -> Self {
+        Self {
+            zenlen,
+            default_length: DivisorClock::Divisor(DEFAULT_LENGTH),
+            default_dots: 0,
+        }
+    }
+
+    // length omitted: l is used, dots are added to the dots of l
+    pub fn resolve(
+        &self,
+        length: Option<&DivisorClock<u8>>,
+        dots: u8,
+    ) -> Result<Clock, SemanticError> {
+        match length {
+            Some(length) => self.resolve_length(length, dots),
+            None => self.resolve_length(&self.default_length, self.default_dots + dots),
+        }
+    }
+
+    fn resolve_length(&self, length: &DivisorClock<u8>, dots: u8) -> Result<Clock, SemanticError> {
+        let base = match length {
+            DivisorClock::Clock(clocks) => *clocks as Clock,
+            DivisorClock::Divisor(divisor) => {
+                if *divisor == 0 || !self.zenlen.is_multiple_of(*divisor) {
+                    return Err(SemanticError::NotDivisor(*divisor, self.zenlen));
+                }
+
+                (self.zenlen / divisor) as Clock
+            }
+        };
+
+        Self::apply_dots(base, dots)
+    }
+
+    // each dot adds the half of the previous one
+    pub fn apply_dots(base: Clock, dots: u8) -> Result<Clock, SemanticError> {
+        let mut total = base;
+        let mut add = base;
+        for _ in 0..dots {
+            if !add.is_multiple_of(2) {
+                return Err(SemanticError::IndivisibleDots(base));
+            }
+
+            add /= 2;
+            total += add;
+        }
+
+        Ok(total)
+    }
+}
+
+pub struct LengthResolver<'a> {
+    pass2: &'a Pass2Result,
+    options: &'a CompileOptions,
+}
+
+impl<'a> LengthResolver<'a> {
+    pub fn new(pass2: &'a Pass2Result, options: &'a CompileOptions) -> Self {
+        Self { pass2, options }
+    }
+
+    pub fn zenlen(&self) -> u8 {
+        self.pass2
+            .find_macros("Zenlen")
+            .last()
+            .map(|m| ZenLenMacro::from((*m).clone()).value)
+            .unwrap_or(DEFAULT_ZENLEN)
+    }
+
+    pub fn resolve(&self) -> (Vec<PartEvents>, Vec<Diagnostic>) {
+        let zenlen = self.zenlen();
+        let mut states: Vec<(PartSymbol, LengthState)> = vec![];
+        let mut parts = vec![];
+        let mut diagnostics = vec![];
+
+        for (part, commands) in &self.pass2.parts {
+            // a part written over several lines continues its state
+            let index = match states.iter().position(|(s, _)| s == part) {
+                Some(index) => index,
+                None => {
+                    states.push((part.clone(), LengthState::new(zenlen)));
+                    states.len() - 1
+                }
+            };
+
+            let events =
+                self.resolve_commands(part, commands, &mut states[index].1, &mut diagnostics);
+            parts.push((part.clone(), events));
+        }
+
+        (parts, diagnostics)
+    }
+
+    fn resolve_commands(
+        &self,
+        part: &PartSymbol,
+        commands: &[WrappedPartCommand],
+        state: &mut LengthState,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) -> Vec<Event> {
+        let mut events = vec![];
+
+        for command in commands {
+            let code = command.code();
+            let data = command.data();
+            let kind = match data {
+                PartCommand::Note(_)
+                | PartCommand::NoteX(_)
+                | PartCommand::NoteR(_)
+                | PartCommand::Portamento(_)
+                | PartCommand::Alpeggio(_) => {
+                    let (length, dots) = Self::length(data);
+                    let clocks = state.resolve(length.as_ref(), dots).unwrap_or_else(|e| {
+                        diagnostics.push(Diagnostic::error(code, Some(part.clone()), e));
+                        0
+                    });
+
+                    match data {
+                        PartCommand::NoteR(_) => EventKind::Rest {
+                            command: data.clone(),
+                            clocks,
+                        },
+                        PartCommand::Portamento(p) => EventKind::Note {
+                            command: data.clone(),
+                            clocks,
+                            pitch: None,
+                            gate: None,
+                            legato: false,
+                            volume: None,
+                            portamento: match portamento::resolve(p, clocks, state) {
+                                Ok(p) => Some(p),
+                                Err(e) => {
+                                    diagnostics.push(Diagnostic::error(
+                                        code,
+                                        Some(part.clone()),
+                                        e,
+                                    ));
+                                    None
+                                }
+                            },
+                        },
+                        PartCommand::Alpeggio(a) => EventKind::Note {
+                            command: match alpeggio::normalize(a, clocks, state) {
+                                Ok(a) => a.to_variant(),
+                                Err(e) => {
+                                    diagnostics.push(Diagnostic::error(
+                                        code,
+                                        Some(part.clone()),
+                                        e,
+                                    ));
+                                    data.clone()
+                                }
+                            },
+                            clocks,
+                            pitch: None,
+                            gate: None,
+                            legato: false,
+                            volume: None,
+                            portamento: None,
+                        },
+                        _ => EventKind::Note {
+                            command: data.clone(),
+                            clocks,
+                            pitch: None,
+                            gate: None,
+                            legato: false,
+                            volume: None,
+                            portamento: None,
+                        },
+                    }
+                }
+                PartCommand::DefaultLength(l) => {
+                    if let Some(length) = &l.length {
+                        match state.resolve(Some(length), l.dots) {
+                            Ok(_) => {
+                                state.default_length = length.clone();
+                                state.default_dots = l.dots;
+                            }
+                            Err(e) => {
+                                diagnostics.push(Diagnostic::error(code, Some(part.clone()), e))
+                            }
+                        }
+                    }
+                    EventKind::Command(data.clone())
+                }
+                PartCommand::WholeLength(c) => {
+                    if c.value == 0 {
+                        diagnostics.push(Diagnostic::error(
+                            code,
+                            Some(part.clone()),
+                            SemanticError::OutOfRange(c.command.clone(), 0, 1, 255),
+                        ));
+                    } else {
+                        state.zenlen = c.value;
+                    }
+                    EventKind::Command(data.clone())
+                }
+                PartCommand::LocalLoop(l) => EventKind::Loop {
+                    count: Some(l.effective_count),
+                    body_pre: self.resolve_commands(part, &l.body_pre, state, diagnostics),
+                    body_post: self.resolve_commands(part, &l.body_post, state, diagnostics),
+                },
+                // c4&8 is the same as c4&c8
+                PartCommand::Tie(Tie {
+                    length: Some(length),
+                    dots,
+                    ..
+                })
+                | PartCommand::Slur(Slur {
+                    length: Some(length),
+                    dots,
+                    ..
+                }) => {
+                    events.push(Event::new(code, EventKind::Command(data.clone())));
+                    match Self::repeat_last_note(data, *length, dots, state, &events) {
+                        Ok(kind) => kind,
+                        Err(e) => {
+                            diagnostics.push(Diagnostic::error(code, Some(part.clone()), e));
+                            continue;
+                        }
+                    }
+                }
+                PartCommand::ProcessLastLengthUpdate(_)
+                | PartCommand::ProcessLastLengthAdd(_)
+                | PartCommand::ProcessLastLengthSubtract(_)
+                | PartCommand::ProcessLastLengthMultiply(_) => {
+                    if let Err(e) = Self::modify_last_length(data, state, &mut events) {
+                        diagnostics.push(Diagnostic::error(code, Some(part.clone()), e));
+                    }
+                    continue;
+                }
+                _ => EventKind::Command(data.clone()),
+            };
+
+            events.push(Event::new(code, kind));
+        }
+
+        self.check_steps(part, events, diagnostics)
+    }
+
+    fn repeat_last_note(
+        command: &PartCommand,
+        length: u8,
+        dots: &Option<String>,
+        state: &LengthState,
+        events: &[Event],
+    ) -> Result<EventKind, SemanticError> {
+        let Some(last) = events
+            .iter()
+            .rev()
+            .find(|e| matches!(e.kind, EventKind::Note { .. }))
+        else {
+            return Err(SemanticError::NothingToModify(command.command_name()));
+        };
+
+        let clocks = state.resolve(
+            Some(&DivisorClock::Divisor(length)),
+            count_dots(dots.clone()),
+        )?;
+        Ok(last.kind.with_clocks(clocks))
+    }
+
+    // ^ l= l+ l- l^ change the length of the note or rest right before them
+    fn modify_last_length(
+        command: &PartCommand,
+        state: &LengthState,
+        events: &mut [Event],
+    ) -> Result<(), SemanticError> {
+        let name = command.command_name();
+        let Some(last) = events.last_mut() else {
+            return Err(SemanticError::NothingToModify(name));
+        };
+        let Some(clocks) = last.clocks() else {
+            return Err(SemanticError::NothingToModify(name));
+        };
+
+        let clocks = clocks as i64;
+        let modified = match command {
+            PartCommand::ProcessLastLengthUpdate(u) => {
+                state.resolve(u.length.as_ref(), u.dots)? as i64
+            }
+            PartCommand::ProcessLastLengthAdd(a) => {
+                clocks + state.resolve(a.length.as_ref(), a.dots)? as i64
+            }
+            PartCommand::ProcessLastLengthSubtract(s) => {
+                clocks - state.resolve(s.length.as_ref(), s.dots)? as i64
+            }
+            PartCommand::ProcessLastLengthMultiply(m) => clocks * m.value as i64,
+            _ => clocks,
+        };
+
+        if modified <= 0 {
+            return Err(SemanticError::NonPositiveLength(name, modified));
+        }
+
+        last.kind = last.kind.with_clocks(modified as Clock);
+        Ok(())
+    }
+
+    fn check_steps(
+        &self,
+        part: &PartSymbol,
+        events: Vec<Event>,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) -> Vec<Event> {
+        let mut checked = vec![];
+
+        for event in events {
+            if let Some(clocks) = event.clocks()
+                && clocks > MAX_STEPS
+            {
+                if self.options.split_long_notes
+                    && let Some(split) = Self::split(&event)
+                {
+                    checked.extend(split);
+                    continue;
+                }
+
+                diagnostics.push(Diagnostic::error(
+                    &event.code,
+                    Some(part.clone()),
+                    SemanticError::TooLong(clocks),
+                ));
+            }
+
+            checked.push(event);
+        }
+
+        checked
+    }
+
+    // same as MC: c&c for notes, r r for rests, every piece keeps the code of the original
+    fn split(event: &Event) -> Option<Vec<Event>> {
+        let clocks = match &event.kind {
+            EventKind::Note {
+                command: PartCommand::Note(_) | PartCommand::NoteX(_),
+                clocks,
+                ..
+            }
+            | EventKind::Rest { clocks, .. } => *clocks,
+            _ => return None,
+        };
+        let is_rest = matches!(event.kind, EventKind::Rest { .. });
+
+        let mut events = vec![];
+        let mut remain = clocks;
+        while remain > 0 {
+            let step = remain.min(MAX_STEPS);
+            if !events.is_empty() && !is_rest {
+                let tie = Tie {
+                    command: "&".to_string(),
+                    length: None,
+                    dots: None,
+                };
+                events.push(Event::split(
+                    &event.code,
+                    EventKind::Command(tie.to_variant()),
+                ));
+            }
+
+            events.push(Event::split(&event.code, event.kind.with_clocks(step)));
+            remain -= step;
+        }
+
+        Some(events)
+    }
+
+    fn length(command: &PartCommand) -> (Option<DivisorClock<u8>>, u8) {
+        match command {
+            PartCommand::Note(n) => (n.length.clone(), n.dots),
+            PartCommand::NoteX(n) => (n.length.clone(), n.dots),
+            PartCommand::NoteR(r) => (r.length.clone(), r.dots),
+            PartCommand::Portamento(p) => (p.length1.map(DivisorClock::Divisor), p.dots),
+            PartCommand::Alpeggio(a) => (a.length1.clone(), a.dots),
+            _ => (None, 0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        commands::{
+            commands_loop::LocalLoop,
+            commands_mml::{DefaultLength, NoteR},
+        },
+        meta_models::Code,
+    };
+
+    use super::*;
+
+    fn clocks(events: &[Event]) -> Vec<Clock> {
+        events.iter().filter_map(|e| e.clocks()).collect()
+    }
+
+    #[test]
+    fn test_resolve_length() {
+        let state = LengthState::new(96);
+        assert_eq!(Ok(24), state.resolve(None, 0));
+        assert_eq!(Ok(36), state.resolve(None, 1));
+        assert_eq!(Ok(12), state.resolve(Some(&DivisorClock::Divisor(8)), 0));
+        assert_eq!(Ok(21), state.resolve(Some(&DivisorClock::Divisor(8)), 2));
+        assert_eq!(Ok(5), state.resolve(Some(&DivisorClock::Clock(5)), 0));
+        assert_eq!(
+            Err(SemanticError::NotDivisor(5, 96)),
+            state.resolve(Some(&DivisorClock::Divisor(5)), 0)
+        );
+        assert_eq!(
+            Err(SemanticError::IndivisibleDots(3)),
+            state.resolve(Some(&DivisorClock::Divisor(32)), 1)
+        );
+    }
+
+    #[test]
+    fn test_resolve_part() {
+        let pass2 = crate::parse("", "A\tc l8 d e. C192 f l%5 g\nA\ta c%12\n").unwrap();
+        let (parts, diagnostics) =
+            LengthResolver::new(&pass2, &CompileOptions::default()).resolve();
+
+        assert!(diagnostics.is_empty());
+        assert_eq!(2, parts.len());
+        assert_eq!(vec![24, 12, 18, 24, 5], clocks(&parts[0].1));
+        assert_eq!(vec![5, 12], clocks(&parts[1].1));
+    }
+
+    #[test]
+    fn test_resolve_zenlen_macro() {
+        let pass2 = crate::parse("", "#Zenlen\t192\n\nA\tc1 c1. d5\n").unwrap();
+        let (parts, diagnostics) =
+            LengthResolver::new(&pass2, &CompileOptions::default()).resolve();
+
+        assert_eq!(vec![192, 288, 0], clocks(&parts[0].1));
+        assert_eq!(2, diagnostics.len());
+        assert_eq!(SemanticError::NotDivisor(5, 192), diagnostics[0].error);
+        assert_eq!(SemanticError::TooLong(288), diagnostics[1].error);
+    }
+
+    #[test]
+    fn test_resolve_inside_loop() {
+        let code = Code::default();
+        let rest = NoteR {
+            command: "r".to_string(),
+            length: None,
+            dots: 0,
+        };
+        let default_length = DefaultLength {
+            command: "l".to_string(),
+            length: Some(DivisorClock::Divisor(16)),
+            dots: 0,
+        };
+        let local_loop = LocalLoop {
+            begin_command: "[".to_string(),
+            body_pre: vec![
+                WrappedPartCommand::new(&code, rest.clone().to_variant()),
+                WrappedPartCommand::new(&code, default_length.to_variant()),
+            ],
+            separator: None,
+            body_post: vec![],
+            end_command: "]".to_string(),
+            count: Some(2),
+            effective_count: 2,
+        };
+
+        let mut pass2 = Pass2Result::default();
+        pass2.parts.push((
+            PartSymbol::A,
+            vec![
+                WrappedPartCommand::new(&code, local_loop.to_variant()),
+                WrappedPartCommand::new(&code, rest.to_variant()),
+            ],
+        ));
+
+        let (parts, _) = LengthResolver::new(&pass2, &CompileOptions::default()).resolve();
+        let events = &parts[0].1;
+        match &events[0].kind {
+            EventKind::Loop { body_pre, .. } => assert_eq!(vec![24], clocks(body_pre)),
+            _ => panic!("not a loop"),
+        }
+        // l is a compile time state, the loop is not repeated here
+        assert_eq!(vec![6], clocks(&events[1..]));
+    }
+
+    #[test]
+    fn test_split_long_notes() {
+        let pass2 = crate::parse("", "#Zenlen\t192\n\nA\tc1. r1. d2.\n").unwrap();
+
+        let options = CompileOptions {
+            split_long_notes: true,
+            ..Default::default()
+        };
+        let (parts, diagnostics) = LengthResolver::new(&pass2, &options).resolve();
+        assert!(diagnostics.is_empty());
+
+        let events = &parts[0].1;
+        assert_eq!(vec![255, 33, 255, 33, 144], clocks(events));
+        assert!(matches!(
+            &events[1].kind,
+            EventKind::Command(PartCommand::Tie(_))
+        ));
+        assert!(matches!(&events[3].kind, EventKind::Rest { .. }));
+        assert!(matches!(&events[4].kind, EventKind::Rest { .. }));
+        assert!(events[..5].iter().all(|e| e.split));
+        assert!(!events[5].split);
+
+        // pieces point at the original note
+        assert_eq!(events[0].code, events[2].code);
+        assert_ne!(events[0].code, events[3].code);
+
+        let (_, diagnostics) = LengthResolver::new(&pass2, &CompileOptions::default()).resolve();
+        assert_eq!(2, diagnostics.len());
+    }
+
+    #[test]
+    fn test_modify_last_length() {
+        let pass2 = crate::parse("", "A c^8 r2 l=16. d8 l+ l-%6 e16 l^3\nB l16 c4^^.\n").unwrap();
+
+        let (parts, diagnostics) =
+            LengthResolver::new(&pass2, &CompileOptions::default()).resolve();
+        assert!(diagnostics.is_empty());
+
+        // modifiers are applied and dropped from the stream
+        let events = &parts[0].1;
+        assert_eq!(4, events.len());
+        assert_eq!(vec![36, 9, 30, 18], clocks(events));
+        assert!(matches!(events[1].kind, EventKind::Rest { .. }));
+
+        // ^ without a length adds the default length
+        assert_eq!(vec![24 + 6 + 9], clocks(&parts[1].1));
+    }
+
+    #[test]
+    fn test_modify_last_length_errors() {
+        let pass2 = crate::parse("", "A l^2 c8 l-8 d1 l^3\n").unwrap();
+
+        let (parts, diagnostics) =
+            LengthResolver::new(&pass2, &CompileOptions::default()).resolve();
+        assert_eq!(vec![12, 288], clocks(&parts[0].1));
+
+        assert_eq!(3, diagnostics.len());
+        assert_eq!(
+            SemanticError::NothingToModify("l^".to_string()),
+            diagnostics[0].error
+        );
+        assert_eq!(
+            SemanticError::NonPositiveLength("l-".to_string(), 0),
+            diagnostics[1].error
+        );
+        // the 255 steps limit is checked with the final length
+        assert_eq!(SemanticError::TooLong(288), diagnostics[2].error);
+    }
+}
@@ -0,0 +1,405 @@
+use crate::{
+    commands::commands_lfo::SoftwareLfo,
+    envelope::envelope_ticks,
+    events::Clock,
+    meta_models::Pass2Result,
+    models::{ExtendNormalOption, LfoSpeedMacro},
+    part_command::PartCommand,
+};
+
+// the last #LFOSpeed, Normal when not given
+pub fn lfo_speed(pass2: &Pass2Result) -> ExtendNormalOption {
+    pass2
+        .find_macros("LFOSpeed")
+        .last()
+        .map(|m| LfoSpeedMacro::from((*m).clone()).value)
+        .unwrap_or(ExtendNormalOption::Normal)
+}
+
+// LFO ticks while `clocks` are played at `timer_b`, Extend runs on the same
+// fixed interrupt as the software envelope
+pub fn lfo_ticks(lfo: &Lfo, clocks: Clock, timer_b: u8) -> u32 {
+    envelope_ticks(&lfo.speed_mode, clocks, timer_b)
+}
+
+// MW
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LfoWaveform {
+    #[default]
+    Triangle1,
+    Sawtooth,
+    Square,
+    Random,
+    Triangle2,
+    Triangle3,
+    OneShot,
+}
+
+impl LfoWaveform {
+    pub fn from_value(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(LfoWaveform::Triangle1),
+            1 => Some(LfoWaveform::Sawtooth),
+            2 => Some(LfoWaveform::Square),
+            3 => Some(LfoWaveform::Random),
+            4 => Some(LfoWaveform::Triangle2),
+            5 => Some(LfoWaveform::Triangle3),
+            6 => Some(LfoWaveform::OneShot),
+            _ => None,
+        }
+    }
+}
+
+// one software LFO as set by M, MW and MX.
+// the offsets are in the unit of the target, detune for pitch, v or V for volume
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lfo {
+    pub delay: u8,
+    pub speed: u8,
+    pub depth1: i8,
+    pub depth2: u8,
+    pub waveform: LfoWaveform,
+    pub speed_mode: ExtendNormalOption,
+}
+
+impl Lfo {
+    pub fn new(speed_mode: ExtendNormalOption) -> Self {
+        Self {
+            delay: 0,
+            speed: 0,
+            depth1: 0,
+            depth2: 0,
+            waveform: LfoWaveform::default(),
+            speed_mode,
+        }
+    }
+
+    pub fn set(&mut self, m: &SoftwareLfo) {
+        self.delay = m.delay;
+        self.speed = m.speed;
+        self.depth1 = m.depth1;
+        self.depth2 = m.depth2;
+    }
+}
+
+// LFO A and B of a part
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LfoState {
+    pub a: Lfo,
+    pub b: Lfo,
+}
+
+impl LfoState {
+    pub fn new(speed_mode: ExtendNormalOption) -> Self {
+        Self {
+            a: Lfo::new(speed_mode.clone()),
+            b: Lfo::new(speed_mode),
+        }
+    }
+
+    pub fn apply(&mut self, command: &PartCommand) {
+        match command {
+            PartCommand::SoftwareLfoA(m) => self.a.set(m),
+            PartCommand::SoftwareLfoB(m) => self.b.set(m),
+            PartCommand::LfoWaveformA(c) => {
+                self.a.waveform = LfoWaveform::from_value(c.value).unwrap_or(self.a.waveform)
+            }
+            PartCommand::LfoWaveformB(c) => {
+                self.b.waveform = LfoWaveform::from_value(c.value).unwrap_or(self.b.waveform)
+            }
+            PartCommand::LfoSpeedModeA(c) => self.a.speed_mode = speed_mode(c.value),
+            PartCommand::LfoSpeedModeB(c) => self.b.speed_mode = speed_mode(c.value),
+            _ => {}
+        }
+    }
+}
+
+fn speed_mode(value: u8) -> ExtendNormalOption {
+    match value {
+        0 => ExtendNormalOption::Normal,
+        _ => ExtendNormalOption::Extend,
+    }
+}
+
+// the LFO of one note, key on when created
+#[derive(Debug, Clone)]
+pub struct LfoGenerator {
+    lfo: Lfo,
+    delay: u8,
+    counter: u8,
+    // steps left until the waveform turns
+    steps: u8,
+    direction: i32,
+    value: i32,
+    seed: u32,
+}
+
+impl LfoGenerator {
+    pub fn new(lfo: Lfo) -> Self {
+        // triangle 1, 2 and the sawtooth start from the middle of their swing
+        let steps = match lfo.waveform {
+            LfoWaveform::Triangle1 | LfoWaveform::Triangle2 | LfoWaveform::Sawtooth => {
+                (lfo.depth2 / 2).max(1)
+            }
+            _ => lfo.depth2,
+        };
+
+        Self {
+            delay: lfo.delay,
+            lfo,
+            counter: 0,
+            steps,
+            direction: 1,
+            value: 0,
+            // fixed, so that a preview draws the same random wave every time
+            seed: 0x2545_f491,
+        }
+    }
+
+    pub fn value(&self) -> i32 {
+        self.value
+    }
+
+    pub fn tick(&mut self) {
+        if self.delay > 0 {
+            self.delay -= 1;
+            return;
+        }
+
+        self.counter += 1;
+        if self.counter < self.lfo.speed.max(1) {
+            return;
+        }
+
+        self.counter = 0;
+        self.step();
+    }
+
+    fn step(&mut self) {
+        let depth1 = self.lfo.depth1 as i32;
+        let amount = match self.lfo.waveform {
+            // the change amount is squared for a deeper swing
+            LfoWaveform::Triangle2 => depth1 * depth1.abs(),
+            _ => depth1,
+        };
+
+        match self.lfo.waveform {
+            LfoWaveform::Triangle1 | LfoWaveform::Triangle2 | LfoWaveform::Triangle3 => {
+                self.value += amount * self.direction;
+                self.turn();
+            }
+            LfoWaveform::Sawtooth => {
+                if self.steps == 0 {
+                    // jumps back to the bottom
+                    self.value = -self.value;
+                    self.steps = self.lfo.depth2;
+                } else {
+                    self.value += amount;
+                    self.steps -= 1;
+                }
+            }
+            LfoWaveform::Square => {
+                self.value = amount * self.direction;
+                self.turn();
+            }
+            LfoWaveform::Random => {
+                let range = amount.abs() * self.lfo.depth2.max(1) as i32;
+                self.value = (self.random() % (range * 2 + 1) as u32) as i32 - range;
+            }
+            LfoWaveform::OneShot => {
+                if self.steps > 0 {
+                    self.value += amount;
+                    self.steps -= 1;
+                }
+            }
+        }
+    }
+
+    // 0 of depth2 never turns
+    fn turn(&mut self) {
+        if self.lfo.depth2 == 0 {
+            return;
+        }
+
+        self.steps = self.steps.saturating_sub(1);
+        if self.steps == 0 {
+            self.direction = -self.direction;
+            self.steps = self.lfo.depth2;
+        }
+    }
+
+    // xorshift32
+    fn random(&mut self) -> u32 {
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 17;
+        self.seed ^= self.seed << 5;
+        self.seed
+    }
+}
+
+// offset of each tick of a note of `ticks`
+pub fn lfo_curve(lfo: &Lfo, ticks: u32) -> Vec<i32> {
+    let mut generator = LfoGenerator::new(lfo.clone());
+
+    (0..ticks)
+        .map(|_| {
+            let value = generator.value();
+            generator.tick();
+            value
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lfo(waveform: LfoWaveform, delay: u8, speed: u8, depth1: i8, depth2: u8) -> Lfo {
+        Lfo {
+            delay,
+            speed,
+            depth1,
+            depth2,
+            waveform,
+            speed_mode: ExtendNormalOption::Normal,
+        }
+    }
+
+    #[test]
+    fn test_lfo_triangle() {
+        assert_eq!(
+            vec![0, 0, 0, 1, 2, 1, 0, -1, -2, -1, 0, 1],
+            lfo_curve(&lfo(LfoWaveform::Triangle1, 2, 1, 1, 4), 12)
+        );
+        // changes every 2 ticks
+        assert_eq!(
+            vec![0, 0, -3, -3, -6, -6, -3, -3],
+            lfo_curve(&lfo(LfoWaveform::Triangle1, 0, 2, -3, 4), 8)
+        );
+        assert_eq!(
+            vec![0, 4, 8, 4, 0, -4],
+            lfo_curve(&lfo(LfoWaveform::Triangle2, 0, 1, 2, 4), 6)
+        );
+        assert_eq!(
+            vec![0, 1, 2, 1, 0, 1],
+            lfo_curve(&lfo(LfoWaveform::Triangle3, 0, 1, 1, 2), 6)
+        );
+    }
+
+    #[test]
+    fn test_lfo_waveforms() {
+        assert_eq!(
+            vec![0, 1, 2, -2, -1, 0, 1, 2, -2],
+            lfo_curve(&lfo(LfoWaveform::Sawtooth, 0, 1, 1, 4), 9)
+        );
+        assert_eq!(
+            vec![0, 3, 3, -3, -3, 3],
+            lfo_curve(&lfo(LfoWaveform::Square, 0, 1, 3, 2), 6)
+        );
+        assert_eq!(
+            vec![0, 0, 2, 4, 6, 6, 6],
+            lfo_curve(&lfo(LfoWaveform::OneShot, 1, 1, 2, 3), 7)
+        );
+
+        let random = lfo_curve(&lfo(LfoWaveform::Random, 0, 1, 2, 3), 32);
+        assert!(random.iter().all(|v| (-6..=6).contains(v)));
+        assert!(random.iter().any(|v| *v != random[1]));
+        assert_eq!(random, lfo_curve(&lfo(LfoWaveform::Random, 0, 1, 2, 3), 32));
+    }
+
+    #[test]
+    fn test_lfo_from_commands() {
+        let mml = "#LFOSpeed Extend\nA M2,1,+3,4 MW4 MB0,2,-128,2 MWB2 MXB0 c";
+        let pass2 = crate::parse("", mml).unwrap();
+
+        let mut state = LfoState::new(lfo_speed(&pass2));
+        for (_, commands) in &pass2.parts {
+            commands.iter().for_each(|c| state.apply(c.data()));
+        }
+
+        assert_eq!(
+            Lfo {
+                delay: 2,
+                speed: 1,
+                depth1: 3,
+                depth2: 4,
+                waveform: LfoWaveform::Triangle2,
+                speed_mode: ExtendNormalOption::Extend,
+            },
+            state.a
+        );
+        assert_eq!(
+            Lfo {
+                delay: 0,
+                speed: 2,
+                depth1: -128,
+                depth2: 2,
+                waveform: LfoWaveform::Square,
+                speed_mode: ExtendNormalOption::Normal,
+            },
+            state.b
+        );
+
+        assert_eq!(43, lfo_ticks(&state.a, 48, 200));
+        assert_eq!(48, lfo_ticks(&state.b, 48, 200));
+    }
+
+    #[test]
+    fn test_lfo_commands_parse() {
+        use crate::commands::commands_lfo::{LfoDepthChange, LfoOption, PitchLfo, SoftwareLfo};
+
+        let mml = "A M 0,1,2,3 MP-128 MPB4,2,1 MD1 MDA2,-3,4 MMB3 MW 2 c";
+        let pass2 = crate::parse("", mml).unwrap();
+        let commands = pass2.parts[0]
+            .1
+            .iter()
+            .map(|c| c.data().clone())
+            .filter(|c| !matches!(c, PartCommand::Note(_)))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            vec![
+                PartCommand::SoftwareLfoA(SoftwareLfo {
+                    command: "M".to_string(),
+                    delay: 0,
+                    speed: 1,
+                    depth1: 2,
+                    depth2: 3,
+                }),
+                PartCommand::PitchLfoA(PitchLfo {
+                    command: "MP".to_string(),
+                    depth: -128,
+                    delay: None,
+                    speed: None,
+                }),
+                PartCommand::PitchLfoB(PitchLfo {
+                    command: "MPB".to_string(),
+                    depth: 4,
+                    delay: Some(2),
+                    speed: Some(1),
+                }),
+                PartCommand::LfoDepthChangeA(LfoDepthChange {
+                    command: "MD".to_string(),
+                    speed: 1,
+                    depth: None,
+                    count: None,
+                }),
+                PartCommand::LfoDepthChangeA(LfoDepthChange {
+                    command: "MDA".to_string(),
+                    speed: 2,
+                    depth: Some(-3),
+                    count: Some(4),
+                }),
+                PartCommand::LfoSlotMaskB(LfoOption {
+                    command: "MMB".to_string(),
+                    value: 3,
+                }),
+                PartCommand::LfoWaveformA(LfoOption {
+                    command: "MW".to_string(),
+                    value: 2,
+                }),
+            ],
+            commands
+        );
+    }
+}
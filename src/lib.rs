@@ -1,14 +1,36 @@
+mod alpeggio;
 mod consts;
+mod diagnostics;
+mod envelope;
 mod errors;
+mod events;
+mod gate;
+mod jump;
+mod length;
+mod lfo;
+mod measure;
+mod loop_check;
+mod loop_sync;
 mod meta_models;
+mod metadata;
 mod models;
+mod options;
 #[macro_use]
 mod part_command;
 mod command_spec;
 mod commands;
 mod pass1;
 mod pass2;
+mod pitch;
+mod portamento;
+mod report;
+mod target;
+mod tie;
+mod timeline;
+mod unroll;
 mod utils;
+mod validation;
+mod volume;
 
 use std::{
     fs::{self},
@@ -17,6 +39,33 @@ use std::{
 
 use crate::{meta_models::Code, pass1::Pass1};
 
+pub use crate::{
+    diagnostics::{Diagnostic, Severity},
+    envelope::{
+        EnvelopeGenerator, EnvelopePhase, SoftwareEnvelope, envelope_curve, envelope_speed,
+        envelope_ticks,
+    },
+    events::{Clock, Event, EventKind, PartEvents},
+    gate::{GateResolver, GateState},
+    length::{LengthResolver, LengthState},
+    lfo::{Lfo, LfoGenerator, LfoState, LfoWaveform, lfo_curve, lfo_speed, lfo_ticks},
+    loop_sync::check_loop_sync,
+    measure::Position,
+    meta_models::{Pass1Result, Pass2Result},
+    metadata::{SongMetadata, load_metadata_from_file},
+    options::{CompileOptions, CompileSwitch, OPTION_ENVIRONMENT, ToneFormat},
+    pass2::Pass2,
+    pitch::{Pitch, PitchResolver, PitchState},
+    portamento::{PortamentoSlide, ResolvedPortamento},
+    report::{PartReport, Report},
+    target::{TargetDriver, TargetProfile},
+    tie::TieMerger,
+    timeline::{PartTimeline, TempoChange, TimedEvent, Timeline},
+    unroll::LoopUnroller,
+    validation::Validator,
+    volume::{VolumeDown, VolumeResolver, VolumeState},
+};
+
 pub fn load_from_file(path: PathBuf) -> String {
     let file = fs::read(path).expect("Unable to read file");
     let (res, _, had_errors) = encoding_rs::SHIFT_JIS.decode(&file);
@@ -24,14 +73,26 @@ pub fn load_from_file(path: PathBuf) -> String {
         return res.to_string();
     }
 
-    let (res, _, had_errors) = encoding_rs::UTF_8.decode(&file);
+    let (res, _, _) = encoding_rs::UTF_8.decode(&file);
     load(res)
 }
 
+// runs pass1 and pass2 over the whole source
+pub fn parse(file_name: impl Into<String>, mml: impl Into<String>) -> Option<Pass2Result> {
+    let code = Code {
+        file_name: file_name.into(),
+        ..Default::default()
+    };
+    let mml = mml.into();
+
+    let pass1 = Pass1::new(code.clone(), mml.clone()).parse().ok()?;
+    Pass2::new(code, mml, pass1).parse().ok()
+}
+
 pub fn load(mml: impl Into<String>) -> String {
     let code = Code::default();
     let mut pass1 = Pass1::new(code, mml.into());
     let _ = pass1.parse();
 
-    return "".to_string();
+    "".to_string()
 }
@@ -0,0 +1,268 @@
+use std::collections::HashMap;
+
+use crate::{
+    diagnostics::Diagnostic,
+    errors::SemanticError,
+    meta_models::Code,
+    models::{ExtendPartSymbol, PartSymbol},
+};
+
+// nesting depth of [ ] accepted by MC.EXE
+pub const MAX_LOOP_NEST: u8 = 8;
+// ]数値, 0 is an infinite loop
+pub const MAX_LOOP_COUNT: u32 = 255;
+
+// [ waiting for the ]
+struct OpenLoop {
+    line: usize,
+    begin: usize,
+    // line and char of the :
+    separator: Option<(usize, usize)>,
+}
+
+// checks [ : ] of the part lines before pass2 parses them. the broken brackets
+// are blanked out, so the rest of the line is parsed as if they were not there.
+// a loop may span the lines of its part, a [ still open at the end of the mml
+// or a ] with no [ open in its part is broken
+pub fn check_loops(code: &Code, mml: &str) -> (String, Vec<Diagnostic>) {
+    let mut diagnostics = vec![];
+    let mut lines = mml
+        .split('\n')
+        .map(|line| line.chars().collect::<Vec<char>>())
+        .collect::<Vec<_>>();
+    let mut open_loops: HashMap<char, Vec<OpenLoop>> = HashMap::new();
+
+    for (i, line) in lines.iter_mut().enumerate() {
+        let Some(symbol) = part_symbol(line) else {
+            continue;
+        };
+        let loops = open_loops.entry(symbol).or_default();
+        check_line(code, i, line, loops, &mut diagnostics);
+    }
+
+    let mut unmatched = open_loops.into_values().flatten().collect::<Vec<_>>();
+    unmatched.sort_by_key(|open| (open.line, open.begin));
+    for open in unmatched {
+        report(
+            &mut diagnostics,
+            code,
+            open.line,
+            &lines[open.line],
+            open.begin,
+            SemanticError::UnmatchedLoop("[".to_string()),
+        );
+        lines[open.line][open.begin] = ' ';
+        if let Some((line, separator)) = open.separator {
+            lines[line][separator] = ' ';
+        }
+    }
+
+    let mml = lines
+        .into_iter()
+        .map(|line| line.into_iter().collect::<String>())
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    (mml, diagnostics)
+}
+
+// the first char of a part line
+fn part_symbol(line: &[char]) -> Option<char> {
+    let symbol = line.first().filter(|c| c.is_ascii_alphabetic())?;
+    let is_part = symbol.to_string().parse::<PartSymbol>().is_ok()
+        || symbol.to_string().parse::<ExtendPartSymbol>().is_ok();
+
+    is_part.then_some(*symbol)
+}
+
+// chars is a line of the part, for its symbol
+fn report(
+    diagnostics: &mut Vec<Diagnostic>,
+    code: &Code,
+    line: usize,
+    chars: &[char],
+    at: usize,
+    error: SemanticError,
+) {
+    let code = Code {
+        file_name: code.file_name.clone(),
+        lines: code.lines + line,
+        chars: at,
+    };
+    let symbol = chars[0].to_string();
+    let diagnostic = Diagnostic::error(&code, symbol.parse::<PartSymbol>().ok(), error);
+    diagnostics.push(match symbol.parse::<ExtendPartSymbol>() {
+        Ok(part) => diagnostic.in_extend_part(part),
+        Err(_) => diagnostic,
+    });
+}
+
+fn check_line(
+    code: &Code,
+    line: usize,
+    chars: &mut [char],
+    loops: &mut Vec<OpenLoop>,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let mut comment = false;
+    let mut i = 1;
+
+    while i < chars.len() {
+        match chars[i] {
+            '`' => comment = !comment,
+            _ if comment => {}
+            ';' => break,
+            '[' => {
+                loops.push(OpenLoop {
+                    line,
+                    begin: i,
+                    separator: None,
+                });
+                if loops.len() > MAX_LOOP_NEST as usize {
+                    let error = SemanticError::LoopNestTooDeep(MAX_LOOP_NEST);
+                    report(diagnostics, code, line, chars, i, error);
+                }
+            }
+            ':' => match loops.last_mut() {
+                None => {
+                    report(
+                        diagnostics,
+                        code,
+                        line,
+                        chars,
+                        i,
+                        SemanticError::StrayLoopSeparator,
+                    );
+                    chars[i] = ' ';
+                }
+                Some(open) if open.separator.is_some() => {
+                    let error = SemanticError::DuplicateLoopSeparator;
+                    report(diagnostics, code, open.line, chars, open.begin, error);
+                    chars[i] = ' ';
+                }
+                Some(open) => open.separator = Some((line, i)),
+            },
+            ']' => {
+                let end = count_end(chars, i + 1);
+                let Some(open) = loops.pop() else {
+                    // the count belongs to the stray ] as well
+                    let error = SemanticError::UnmatchedLoop("]".to_string());
+                    report(diagnostics, code, line, chars, i, error);
+                    chars[i..end].fill(' ');
+                    i = end;
+                    continue;
+                };
+
+                let count = chars[i + 1..end].iter().collect::<String>();
+                if let Ok(count) = count.parse::<u32>()
+                    && count > MAX_LOOP_COUNT
+                {
+                    let error = SemanticError::OutOfRange(
+                        "]".to_string(),
+                        count.min(i32::MAX as u32) as i32,
+                        0,
+                        MAX_LOOP_COUNT as i32,
+                    );
+                    report(diagnostics, code, open.line, chars, open.begin, error);
+                    chars[i + 1..end].fill(' ');
+                }
+                i = end;
+                continue;
+            }
+            _ => {}
+        }
+
+        i += 1;
+    }
+}
+
+fn count_end(chars: &[char], begin: usize) -> usize {
+    chars[begin..]
+        .iter()
+        .position(|c| !c.is_ascii_digit())
+        .map_or(chars.len(), |n| begin + n)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(mml: &str) -> (String, Vec<(usize, SemanticError)>) {
+        let (mml, diagnostics) = check_loops(&Code::default(), mml);
+        let errors = diagnostics
+            .into_iter()
+            .map(|d| (d.code.chars, d.error))
+            .collect();
+        (mml, errors)
+    }
+
+    #[test]
+    fn test_check_loops() {
+        let mml = "A [c:d]3 [e[f]2]\n#Title [\n; ]\nB c ;]";
+        assert_eq!((mml.to_string(), vec![]), check(mml));
+    }
+
+    #[test]
+    fn test_check_loops_unmatched() {
+        assert_eq!(
+            (
+                "A c    d  e".to_string(),
+                vec![(4, SemanticError::UnmatchedLoop("]".to_string()))]
+            ),
+            check("A c ]2 d  e")
+        );
+        assert_eq!(
+            (
+                "A  c d [e]".to_string(),
+                vec![(2, SemanticError::UnmatchedLoop("[".to_string()))]
+            ),
+            check("A [c:d [e]")
+        );
+    }
+
+    #[test]
+    fn test_check_loops_lines() {
+        let mml = "A [c:\nB d\nA e]2";
+        assert_eq!((mml.to_string(), vec![]), check(mml));
+
+        let (mml, diagnostics) = check_loops(&Code::default(), "A [c\nB ]d\nA [e:f]\nC g");
+        assert_eq!("A  c\nB  d\nA [e:f]\nC g", mml);
+        assert_eq!(
+            vec![
+                (1, 2, SemanticError::UnmatchedLoop("]".to_string())),
+                (0, 2, SemanticError::UnmatchedLoop("[".to_string())),
+            ],
+            diagnostics
+                .into_iter()
+                .map(|d| (d.code.lines, d.code.chars, d.error))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_check_loops_separator() {
+        assert_eq!(
+            (
+                "B  c [d:e f]".to_string(),
+                vec![
+                    (2, SemanticError::StrayLoopSeparator),
+                    (5, SemanticError::DuplicateLoopSeparator)
+                ]
+            ),
+            check("B :c [d:e:f]")
+        );
+    }
+
+    #[test]
+    fn test_check_loops_nest_count() {
+        let (mml, errors) = check("C [[[[[[[[[c]]]]]]]]]256 `[`");
+        assert_eq!("C [[[[[[[[[c]]]]]]]]]    `[`", mml);
+        assert_eq!(
+            vec![
+                (10, SemanticError::LoopNestTooDeep(8)),
+                (2, SemanticError::OutOfRange("]".to_string(), 256, 0, 255)),
+            ],
+            errors
+        );
+    }
+}
@@ -0,0 +1,87 @@
+use crate::{
+    diagnostics::Diagnostic,
+    errors::SemanticError,
+    events::Clock,
+    timeline::{PartTimeline, Timeline},
+};
+
+// parts whose L-to-end length differs from the others drift apart on every
+// loop. the length most parts share is taken as the right one, the first
+// part breaks a tie
+pub fn check_loop_sync(timeline: &Timeline) -> Vec<Diagnostic> {
+    let loops = timeline
+        .parts
+        .iter()
+        .filter_map(|p| Some((p, p.loop_clocks()?)))
+        .collect::<Vec<(&PartTimeline, Clock)>>();
+
+    let Some(&(reference, clocks)) = loops.iter().max_by_key(|(p, clocks)| {
+        let count = loops.iter().filter(|(_, c)| c == clocks).count();
+        let first = loops.iter().position(|(q, _)| q.part == p.part);
+        (count, std::cmp::Reverse(first))
+    }) else {
+        return vec![];
+    };
+
+    loops
+        .iter()
+        .filter(|(_, c)| *c != clocks)
+        .filter_map(|(p, c)| {
+            Some(Diagnostic::warning(
+                &p.loop_point()?.event.code,
+                Some(p.part.clone()),
+                SemanticError::LoopOutOfSync(*c, reference.part.clone(), *c as i64 - clocks as i64),
+            ))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{models::PartSymbol, options::CompileOptions};
+
+    use super::*;
+
+    fn check(mml: &str) -> Vec<(Option<PartSymbol>, SemanticError)> {
+        let pass2 = crate::parse("", mml).unwrap();
+        let (timeline, _) = Timeline::new(&pass2, &CompileOptions::default());
+
+        check_loop_sync(&timeline)
+            .into_iter()
+            .map(|d| (d.part, d.error))
+            .collect()
+    }
+
+    #[test]
+    fn test_loop_sync() {
+        assert!(check("A c4 L c2\nB L [c8]2 c2\nC c1 c2\nA c4").is_empty());
+    }
+
+    #[test]
+    fn test_loop_out_of_sync() {
+        let errors = check("A L c1\nB c4 L c1 c8\nC L c2 c2\nD L r2");
+        assert_eq!(
+            vec![
+                (
+                    Some(PartSymbol::B),
+                    SemanticError::LoopOutOfSync(108, PartSymbol::A, 12)
+                ),
+                (
+                    Some(PartSymbol::D),
+                    SemanticError::LoopOutOfSync(48, PartSymbol::A, -48)
+                ),
+            ],
+            errors
+        );
+
+        // no majority, the first part is taken
+        let errors = check("A L c1\nB L c2");
+        assert_eq!(
+            vec![(
+                Some(PartSymbol::B),
+                SemanticError::LoopOutOfSync(48, PartSymbol::A, -48)
+            )],
+            errors
+        );
+    }
+}
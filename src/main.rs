@@ -0,0 +1,48 @@
+use std::{env, path::PathBuf, process::ExitCode};
+
+use rs_pmd98_parser::{
+    CompileOptions, OPTION_ENVIRONMENT, Report, TargetProfile, Timeline, Validator,
+    check_loop_sync, load_from_file, parse,
+};
+
+// prints the diagnostics and the length of each part, like MC /V.
+// the switches after the file are those of MC, e.g. /P to follow #Jump
+fn main() -> ExitCode {
+    let mut args = env::args().skip(1);
+    let Some(path) = args.next().map(PathBuf::from) else {
+        eprintln!("usage: rs-pmd98-parser <file.mml> [/switches]");
+        return ExitCode::FAILURE;
+    };
+    let switches = args.collect::<Vec<String>>();
+
+    let file_name = path.display().to_string();
+    let Some(pass2) = parse(file_name.clone(), load_from_file(path)) else {
+        eprintln!("{file_name}: failed to parse");
+        return ExitCode::FAILURE;
+    };
+
+    let environment = env::var(OPTION_ENVIRONMENT).ok();
+    let (options, mut diagnostics) = CompileOptions::merge(
+        TargetProfile::default(),
+        environment.as_deref(),
+        &pass2,
+        &switches,
+    );
+    diagnostics.extend(Validator::new(&pass2, &options).validate());
+    let (timeline, timeline_diagnostics) = Timeline::new(&pass2, &options);
+    diagnostics.extend(timeline_diagnostics);
+    diagnostics.extend(check_loop_sync(&timeline));
+    timeline.locate(&mut diagnostics);
+    let report = Report::from_timeline(&pass2, &options, &timeline);
+
+    for diagnostic in &diagnostics {
+        eprintln!("{diagnostic}");
+    }
+    print!("{report}");
+
+    if diagnostics.iter().any(|d| d.is_error()) {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
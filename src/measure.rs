@@ -0,0 +1,105 @@
+use crate::{events::Clock, models::MeasureType};
+
+// place in a part counted in measures, the first measure is 1
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Position {
+    pub measure: MeasureType,
+    // clocks from the start of the measure
+    pub clock: Clock,
+}
+
+impl Default for Position {
+    fn default() -> Self {
+        Self {
+            measure: 1,
+            clock: 0,
+        }
+    }
+}
+
+// PMD has no time signature, a measure is one whole note of C
+#[derive(Debug, Clone)]
+pub struct MeasureCounter {
+    length: Clock,
+    position: Position,
+}
+
+impl MeasureCounter {
+    pub fn new(zenlen: u8) -> Self {
+        Self {
+            length: zenlen.max(1) as Clock,
+            position: Position::default(),
+        }
+    }
+
+    pub fn position(&self) -> Position {
+        self.position
+    }
+
+    pub fn length(&self) -> Clock {
+        self.length
+    }
+
+    // C changes the length of the current measure as well
+    pub fn set_zenlen(&mut self, zenlen: u8) {
+        if zenlen > 0 {
+            self.length = zenlen as Clock;
+            self.advance(0);
+        }
+    }
+
+    pub fn advance(&mut self, clocks: Clock) {
+        let clock = self.position.clock + clocks;
+        let measures = (clock / self.length).min(MeasureType::MAX as Clock) as MeasureType;
+
+        self.position.measure = self.position.measure.saturating_add(measures);
+        self.position.clock = clock % self.length;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_measure_counter() {
+        let mut counter = MeasureCounter::new(96);
+        counter.advance(72);
+        assert_eq!(
+            Position {
+                measure: 1,
+                clock: 72
+            },
+            counter.position()
+        );
+
+        counter.advance(24 + 96 * 2 + 12);
+        assert_eq!(
+            Position {
+                measure: 4,
+                clock: 12
+            },
+            counter.position()
+        );
+
+        // the rest of the measure is counted in the new length
+        counter.advance(36);
+        counter.set_zenlen(48);
+        assert_eq!(
+            Position {
+                measure: 5,
+                clock: 0
+            },
+            counter.position()
+        );
+        counter.set_zenlen(0);
+        counter.advance(24);
+        assert_eq!(
+            Position {
+                measure: 5,
+                clock: 24
+            },
+            counter.position()
+        );
+    }
+}
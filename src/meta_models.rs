@@ -1,6 +1,9 @@
+use std::str::FromStr;
+
+use crate::diagnostics::Diagnostic;
 use crate::models::{
-    Comment1, Comment2, ExtendNormalOption, FmToneDefine, Macro, OnOffOption, PartSymbol,
-    ReverseNormalOption, Variable,
+    Comment1, Comment2, ExtendNormalOption, ExtendPartSymbol, FmToneDefine, Macro, OnOffOption,
+    PartSymbol, ReverseNormalOption, Variable,
 };
 use crate::part_command::{PartCommandStack, PartToken, PartTokenStack, State, WrappedPartCommand};
 
@@ -56,6 +59,10 @@ where
     pub fn data(&self) -> &T {
         &self.data
     }
+
+    pub fn data_mut(&mut self) -> &mut T {
+        &mut self.data
+    }
 }
 
 #[derive(Debug, Default, Clone, PartialEq, Eq)]
@@ -65,6 +72,7 @@ pub struct Token {
     pub chars: String,
 }
 
+#[allow(dead_code)]
 pub(crate) trait TokenTrait {
     fn eat(&mut self, c: char) {
         self.chars_mut().push(c);
@@ -188,8 +196,10 @@ impl TokenStack {
     }
 }
 
-#[derive(Debug)]
+#[allow(dead_code)]
+#[derive(Debug, Default)]
 pub enum Command {
+    #[default]
     Nop,
     Comment1(Code), // ;
     Comment2(Code), // `
@@ -429,16 +439,12 @@ pub enum Command {
     FmToneDefine(Code), // @
 
     Part(Code, PartSymbol),
+    // declared by #FM3Extend or #PPZExtend
+    ExtendPart(Code, ExtendPartSymbol),
 
     Unknown(CommandName, CommandParameter, Code),
 }
 
-impl Default for Command {
-    fn default() -> Self {
-        Self::Nop
-    }
-}
-
 #[derive(Debug, Clone)]
 pub enum VariantValue {
     Unsigned(u8),
@@ -450,6 +456,50 @@ pub enum VariantValue {
     ExtendNormal(ExtendNormalOption),
 }
 
+impl VariantValue {
+    // Pass1 keeps macro values as strings
+    pub fn to_unsigned(&self) -> Option<u8> {
+        match self {
+            VariantValue::Unsigned(v) => Some(*v),
+            VariantValue::String(s) => s.trim().parse::<u8>().ok(),
+            _ => None,
+        }
+    }
+
+    pub fn to_unsigned_short(&self) -> Option<u16> {
+        match self {
+            VariantValue::Unsigned(v) => Some(*v as u16),
+            VariantValue::UnsignedShort(v) => Some(*v),
+            VariantValue::String(s) => s.trim().parse::<u16>().ok(),
+            _ => None,
+        }
+    }
+
+    pub fn to_signed(&self) -> Option<i8> {
+        match self {
+            VariantValue::Signed(v) => Some(*v),
+            VariantValue::String(s) => s.trim().parse::<i8>().ok(),
+            _ => None,
+        }
+    }
+
+    pub fn to_extend_normal(&self) -> Option<ExtendNormalOption> {
+        match self {
+            VariantValue::ExtendNormal(v) => Some(v.clone()),
+            VariantValue::String(s) => ExtendNormalOption::from_str(s.trim()).ok(),
+            _ => None,
+        }
+    }
+
+    pub fn to_reverse_normal(&self) -> Option<ReverseNormalOption> {
+        match self {
+            VariantValue::ReverseNormal(v) => Some(v.clone()),
+            VariantValue::String(s) => ReverseNormalOption::from_str(s.trim()).ok(),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Default, Debug, Clone)]
 pub struct Pass1Result {
     pub macros: Vec<Macro>,
@@ -542,9 +592,19 @@ pub struct Pass2Result {
     pub comment2s: Vec<Comment2>,
 
     pub parts: Vec<(PartSymbol, Vec<WrappedPartCommand>)>,
+    pub extend_parts: Vec<(ExtendPartSymbol, Vec<WrappedPartCommand>)>,
+    // found while parsing, e.g. broken loop brackets
+    pub diagnostics: Vec<Diagnostic>,
 }
 
 impl Pass2Result {
+    pub fn find_macros(&self, key: &str) -> Vec<&Macro> {
+        self.macros
+            .iter()
+            .filter(|m| m.key.eq_ignore_ascii_case(key))
+            .collect::<Vec<&Macro>>()
+    }
+
     pub fn get_parts(&self, part: &PartSymbol) -> Vec<&Vec<WrappedPartCommand>> {
         self.parts
             .iter()
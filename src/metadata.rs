@@ -0,0 +1,175 @@
+use std::{fs, io, path::PathBuf};
+
+use crate::{
+    meta_models::{Code, FileName, VariantValue},
+    models::{
+        ArrangerMacro, ComposerMacro, FileNameMacro, Macro, MemoMacro, PcmFileMacro, PpsFileMacro,
+        TitleMacro,
+    },
+};
+
+// the header tags of a song, read without running Pass1.
+// as in MC, the last one is taken when a tag is given more than once,
+// except #Memo which is kept in order
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SongMetadata {
+    pub title: Option<String>,
+    pub composer: Option<String>,
+    pub arranger: Option<String>,
+    pub memos: Vec<String>,
+    pub file_name: Option<FileName>,
+    // #PCMFile or #PPCFile
+    pub pcm_file: Option<FileName>,
+    pub pps_file: Option<FileName>,
+    pub ppz_files: Vec<FileName>,
+}
+
+impl SongMetadata {
+    // only lines starting with # are read, part lines and tone definitions
+    // are skipped. ` comments may span lines, so they are followed
+    pub fn scan(file_name: impl Into<String>, mml: &str) -> Self {
+        let file_name = file_name.into();
+        let mut metadata = Self::default();
+        let mut in_comment2 = false;
+
+        for (lines, line) in mml.split('\n').enumerate() {
+            if !in_comment2 && let Some(line) = line.strip_prefix('#') {
+                let code = Code {
+                    file_name: file_name.clone(),
+                    lines,
+                    chars: 0,
+                };
+                metadata.apply(code, line);
+                continue;
+            }
+
+            for c in line.chars() {
+                match c {
+                    '`' => in_comment2 = !in_comment2,
+                    ';' if !in_comment2 => break,
+                    _ => {}
+                }
+            }
+        }
+
+        metadata
+    }
+
+    // decodes Shift-JIS, or UTF-8 when it is not
+    pub fn from_bytes(file_name: impl Into<String>, bytes: &[u8]) -> Self {
+        let (mml, _, had_errors) = encoding_rs::SHIFT_JIS.decode(bytes);
+        if had_errors && let Ok(mml) = std::str::from_utf8(bytes) {
+            return Self::scan(file_name, mml);
+        }
+
+        Self::scan(file_name, &mml)
+    }
+
+    fn apply(&mut self, code: Code, line: &str) {
+        let Some((key, value)) = split_macro(line) else {
+            return;
+        };
+        let m = Macro {
+            code,
+            key: key.to_string(),
+            value: VariantValue::String(value.to_string()),
+        };
+
+        match key.to_ascii_lowercase().as_str() {
+            "title" => self.title = Some(TitleMacro::from(m).value),
+            "composer" => self.composer = Some(ComposerMacro::from(m).value),
+            "arranger" => self.arranger = Some(ArrangerMacro::from(m).value),
+            "memo" => self.memos.push(MemoMacro::from(m).value),
+            "filename" => self.file_name = Some(FileNameMacro::from(m).value),
+            "pcmfile" | "ppcfile" => self.pcm_file = Some(PcmFileMacro::from(m).value),
+            "ppsfile" => self.pps_file = Some(PpsFileMacro::from(m).value),
+            // PpzFileMacro panics on a broken list, which an indexer has to live with
+            "ppzfile" => {
+                self.ppz_files = value
+                    .split(',')
+                    .filter(|f| !f.is_empty())
+                    .map(|f| f.to_string())
+                    .collect()
+            }
+            _ => {}
+        }
+    }
+}
+
+pub fn load_metadata_from_file(path: PathBuf) -> io::Result<SongMetadata> {
+    let bytes = fs::read(&path)?;
+    Ok(SongMetadata::from_bytes(path.display().to_string(), &bytes))
+}
+
+// key and value of a # line without the #. the value runs up to a control
+// code other than TAB and ESC
+fn split_macro(line: &str) -> Option<(&str, &str)> {
+    let (key, rest) = line.split_once([' ', '\t'])?;
+    let rest = rest.trim_start_matches([' ', '\t']);
+    let end = rest
+        .find(|c: char| c.is_control() && c != '\t' && c != '\x1b')
+        .unwrap_or(rest.len());
+    let value = rest[..end].trim_end_matches([' ', '\t']);
+
+    if key.is_empty() || value.is_empty() {
+        return None;
+    }
+
+    Some((key, value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scan() {
+        let mml = "#Title\tSong 1\r\n\
+                   #Composer  someone ; not a comment\r\n\
+                   #memo first\r\n\
+                   ` #Title commented\r\n\
+                   #Arranger commented`\r\n\
+                   A l8 cdef`g\r\n\
+                   #Arranger also commented` #Title not a header\r\n\
+                   B cde ; `\r\n\
+                   #Memo second\r\n\
+                   #PPCFile old.ppc\r\n\
+                   #PCMFile drums.pvi\r\n\
+                   #PPSFile effect.pps\r\n\
+                   #PPZFile a.pzi,b.pvi\r\n\
+                   #Filename .m2\r\n\
+                   #Title\r\n\
+                   @0 4 5\r\n\
+                    31 0 0 0 0 0 0 0 0 0";
+
+        assert_eq!(
+            SongMetadata {
+                title: Some("Song 1".to_string()),
+                composer: Some("someone ; not a comment".to_string()),
+                arranger: None,
+                memos: vec!["first".to_string(), "second".to_string()],
+                file_name: Some(".m2".to_string()),
+                pcm_file: Some("drums.pvi".to_string()),
+                pps_file: Some("effect.pps".to_string()),
+                ppz_files: vec!["a.pzi".to_string(), "b.pvi".to_string()],
+            },
+            SongMetadata::scan("test.mml", mml)
+        );
+    }
+
+    #[test]
+    fn test_from_bytes() {
+        let (bytes, _, _) = encoding_rs::SHIFT_JIS.encode("#Title\t曲名\r\nA c\r\n");
+        assert_eq!(
+            Some("曲名".to_string()),
+            SongMetadata::from_bytes("test.mml", &bytes).title
+        );
+
+        // not Shift-JIS
+        let bytes = "#Composer 作曲者\nA c\n".as_bytes();
+        assert_eq!(
+            Some("作曲者".to_string()),
+            SongMetadata::from_bytes("test.mml", bytes).composer
+        );
+    }
+}
@@ -1,3 +1,6 @@
+// the macros of the whole PMD manual are modeled, not all of them are compiled yet
+#![allow(dead_code)]
+
 use std::str::FromStr;
 
 use strum::VariantNames;
@@ -80,6 +83,52 @@ pub enum InstrumentsCategorySymbol {
     R, // Rhythm
 }
 
+// [音源] section of each command
+#[derive(Debug, Clone, Copy, PartialEq, Eq, strum::EnumIter)]
+pub enum Chip {
+    Fm,           // FM
+    Ssg,          // SSG
+    Pcm,          // PCM
+    RhythmSelect, // R選択
+    RhythmDefine, // R定義
+}
+
+impl PartSymbol {
+    pub fn chip(&self) -> Chip {
+        match self {
+            PartSymbol::A
+            | PartSymbol::B
+            | PartSymbol::C
+            | PartSymbol::D
+            | PartSymbol::E
+            | PartSymbol::F => Chip::Fm,
+            PartSymbol::G | PartSymbol::H | PartSymbol::I => Chip::Ssg,
+            PartSymbol::J => Chip::Pcm,
+            PartSymbol::K => Chip::RhythmSelect,
+            PartSymbol::R => Chip::RhythmDefine,
+        }
+    }
+}
+
+impl ExtendPartSymbol {
+    // extended parts only exist when declared by #FM3Extend or #PPZExtend
+    pub fn chip(
+        &self,
+        fm3_extend: Option<&Fm3ExtendMacro>,
+        ppz_extend: Option<&PpzExtendMacro>,
+    ) -> Option<Chip> {
+        if fm3_extend.is_some_and(|m| m.value.contains(self)) {
+            return Some(Chip::Fm);
+        }
+
+        if ppz_extend.is_some_and(|m| m.value.contains(self)) {
+            return Some(Chip::Pcm);
+        }
+
+        None
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum RelativeAbsolute8 {
     Relative(i16),
@@ -130,7 +179,7 @@ impl TryFrom<Vec<PartToken>> for DivisorClock<u8> {
     type Error = Pass2Error;
 
     fn try_from(value: Vec<PartToken>) -> Result<Self, Self::Error> {
-        if value.len() <= 0 {
+        if value.is_empty() {
             panic!("stack has no items");
         }
 
@@ -139,6 +188,15 @@ impl TryFrom<Vec<PartToken>> for DivisorClock<u8> {
         }
 
         if value.len() == 1 {
+            if let Some(clock) = value[0].token().strip_prefix('%') {
+                match clock.parse::<u8>() {
+                    Ok(v) => return Ok(DivisorClock::Clock(v)),
+                    Err(e) => {
+                        panic!("{}", e);
+                    }
+                }
+            }
+
             match value[0].token().parse::<u8>() {
                 Ok(v) => return Ok(DivisorClock::Divisor(v)),
                 Err(e) => {
@@ -181,13 +239,15 @@ pub enum OnOffOption {
     Off,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, strum::EnumString)]
+#[strum(ascii_case_insensitive)]
 pub enum ReverseNormalOption {
     Reverse,
     Normal,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, strum::EnumString)]
+#[strum(ascii_case_insensitive)]
 pub enum ExtendNormalOption {
     Extend,
     Normal,
@@ -530,12 +590,10 @@ pub struct OptionMacro {
 impl From<Macro> for OptionMacro {
     fn from(m: Macro) -> Self {
         if let VariantValue::String(options) = m.value {
-            // TODO: panic when unknwon or invalid option
-
             let values = options
                 .split("/")
                 .map(|e| e.trim().to_string())
-                .filter(|e| e.len() > 0)
+                .filter(|e| !e.is_empty())
                 .collect::<Vec<String>>();
 
             return Self {
@@ -721,7 +779,7 @@ pub struct TempoMacro {
 
 impl From<Macro> for TempoMacro {
     fn from(m: Macro) -> Self {
-        if let VariantValue::Unsigned(value) = m.value {
+        if let Some(value) = m.value.to_unsigned() {
             // TODO: 255 or 250
             // if value < 18 {
             //     panic!("Tempo value is too large");
@@ -729,7 +787,7 @@ impl From<Macro> for TempoMacro {
 
             return Self {
                 code: m.code,
-                value: value as u8,
+                value,
             };
         };
 
@@ -766,14 +824,14 @@ pub struct ZenLenMacro {
 
 impl From<Macro> for ZenLenMacro {
     fn from(m: Macro) -> Self {
-        if let VariantValue::Unsigned(value) = m.value {
+        if let Some(value) = m.value.to_unsigned() {
             if value < 1 {
                 panic!("Zenlen value is too small");
             }
 
             return Self {
                 code: m.code,
-                value: value,
+                value,
             };
         };
 
@@ -800,7 +858,7 @@ pub struct OctaveMacro {
 
 impl From<Macro> for OctaveMacro {
     fn from(m: Macro) -> Self {
-        if let VariantValue::ReverseNormal(value) = m.value {
+        if let Some(value) = m.value.to_reverse_normal() {
             return Self {
                 code: m.code,
                 value,
@@ -831,10 +889,10 @@ pub struct LoopDefaultMacro {
 
 impl From<Macro> for LoopDefaultMacro {
     fn from(m: Macro) -> Self {
-        if let VariantValue::Unsigned(value) = m.value {
+        if let Some(value) = m.value.to_unsigned() {
             return Self {
                 code: m.code,
-                value: value,
+                value,
             };
         };
 
@@ -907,7 +965,7 @@ impl From<Macro> for BendRangeMacro {
         if let VariantValue::Unsigned(value) = m.value {
             return Self {
                 code: m.code,
-                value: value as u8,
+                value,
             };
         };
 
@@ -974,7 +1032,7 @@ pub struct LfoSpeedMacro {
 
 impl From<Macro> for LfoSpeedMacro {
     fn from(m: Macro) -> Self {
-        if let VariantValue::ExtendNormal(value) = m.value {
+        if let Some(value) = m.value.to_extend_normal() {
             return Self {
                 code: m.code,
                 value,
@@ -1005,7 +1063,7 @@ pub struct EnvelopeSpeedMacro {
 
 impl From<Macro> for EnvelopeSpeedMacro {
     fn from(m: Macro) -> Self {
-        if let VariantValue::ExtendNormal(value) = m.value {
+        if let Some(value) = m.value.to_extend_normal() {
             return Self {
                 code: m.code,
                 value,
@@ -1038,7 +1096,7 @@ pub struct PcmVolumeMacro {
 
 impl From<Macro> for PcmVolumeMacro {
     fn from(m: Macro) -> Self {
-        if let VariantValue::ExtendNormal(value) = m.value {
+        if let Some(value) = m.value.to_extend_normal() {
             return Self {
                 code: m.code,
                 value,
@@ -1080,7 +1138,6 @@ impl From<Macro> for Fm3ExtendMacro {
                 .map(|c| c.to_string())
                 .map(|s| {
                     if ExtendPartSymbol::VARIANTS.contains(&s.as_str()) {
-                        println!("{}", s);
                         ExtendPartSymbol::from_str(s.as_str()).unwrap()
                     } else {
                         panic!("Fm3Extend: invalid part symbol: {}", s);
@@ -1117,7 +1174,7 @@ pub struct IncludeMacro {
 }
 
 impl From<Macro> for IncludeMacro {
-    fn from(m: Macro) -> Self {
+    fn from(_m: Macro) -> Self {
         todo!();
     }
 }
@@ -1171,7 +1228,7 @@ impl From<Macro> for VolumeDownMacro {
                         continue;
                     }
 
-                    if ('0'..('9' as u8 + 1) as char).contains(&c) || c == '+' || c == '-' {
+                    if c.is_ascii_digit() || c == '+' || c == '-' {
                         if current_inst_symbol {
                             tokens.push(&token);
                             token.clear();
@@ -1202,7 +1259,7 @@ impl From<Macro> for VolumeDownMacro {
             let mut values = vec![];
             {
                 let mut value = if let Some(t) = tokens.pop() {
-                    Self::token_to_relative_absolue_8(&t.chars.as_str()).unwrap()
+                    Self::token_to_relative_absolue_8(t.chars.as_str()).unwrap()
                 } else {
                     panic!("VolumeDown: no parameters");
                 };
@@ -1236,10 +1293,7 @@ impl From<Macro> for VolumeDownMacro {
 
 impl VolumeDownMacro {
     fn token_to_relative_absolue_8(value: &str) -> Result<RelativeAbsolute8, ()> {
-        match RelativeAbsolute8::try_from(value) {
-            Ok(v) => Ok(v),
-            Err(_) => panic!("VolumeDown: invalid value {}", value),
-        }
+        Ok(RelativeAbsolute8::from(value))
     }
 }
 
@@ -1306,7 +1360,7 @@ pub struct JumpMacro {
 
 impl From<Macro> for JumpMacro {
     fn from(m: Macro) -> Self {
-        if let VariantValue::UnsignedShort(value) = m.value {
+        if let Some(value) = m.value.to_unsigned_short() {
             return Self {
                 code: m.code,
                 value,
@@ -1457,7 +1511,7 @@ pub struct TransposeMacro {
 
 impl From<Macro> for TransposeMacro {
     fn from(m: Macro) -> Self {
-        if let VariantValue::Signed(value) = m.value {
+        if let Some(value) = m.value.to_signed() {
             return Self {
                 code: m.code,
                 value,
@@ -1480,6 +1534,21 @@ pub enum NoteCommand {
     b,
 }
 
+impl NoteCommand {
+    // semitones from c in the same octave
+    pub fn semitone(&self) -> i16 {
+        match self {
+            NoteCommand::c => 0,
+            NoteCommand::d => 2,
+            NoteCommand::e => 4,
+            NoteCommand::f => 5,
+            NoteCommand::g => 7,
+            NoteCommand::a => 9,
+            NoteCommand::b => 11,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Eq, PartialEq, strum::EnumString)]
 #[allow(non_camel_case_types)]
 pub enum NoteOctaveCommand {
@@ -1979,6 +2048,16 @@ mod tests {
 
             assert_eq!(ExtendNormalOption::Extend, m.value);
         }
+
+        {
+            let m = LfoSpeedMacro::from(Macro {
+                code: Code::default(),
+                key: "LFOSpeed".to_owned(),
+                value: VariantValue::String("Extend".to_owned()),
+            });
+
+            assert_eq!(ExtendNormalOption::Extend, m.value);
+        }
     }
 
     #[test]
@@ -2002,6 +2081,16 @@ mod tests {
 
             assert_eq!(ExtendNormalOption::Extend, m.value);
         }
+
+        {
+            let m = EnvelopeSpeedMacro::from(Macro {
+                code: Code::default(),
+                key: "EnvelopeSpeed".to_owned(),
+                value: VariantValue::String("Extend".to_owned()),
+            });
+
+            assert_eq!(ExtendNormalOption::Extend, m.value);
+        }
     }
 
     #[test]
@@ -2025,6 +2114,16 @@ mod tests {
 
             assert_eq!(ExtendNormalOption::Extend, m.value);
         }
+
+        {
+            let m = PcmVolumeMacro::from(Macro {
+                code: Code::default(),
+                key: "PCMVolume".to_owned(),
+                value: VariantValue::String("extend".to_owned()),
+            });
+
+            assert_eq!(ExtendNormalOption::Extend, m.value);
+        }
     }
 
     #[test]
@@ -2185,6 +2284,16 @@ mod tests {
 
             assert_eq!(65535, m.value);
         }
+
+        {
+            let m = JumpMacro::from(Macro {
+                code: Code::default(),
+                key: "Jump".to_owned(),
+                value: VariantValue::String("37".to_owned()),
+            });
+
+            assert_eq!(37, m.value);
+        }
     }
 
     #[test]
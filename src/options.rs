@@ -0,0 +1,300 @@
+use std::str::FromStr;
+
+use crate::{
+    diagnostics::Diagnostic,
+    errors::SemanticError,
+    meta_models::{Code, Pass2Result},
+    models::OptionMacro,
+    target::TargetProfile,
+};
+
+// environment variable read by MC.EXE
+pub const OPTION_ENVIRONMENT: &str = "MCOPT";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, strum::EnumString, strum::Display)]
+#[strum(ascii_case_insensitive)]
+pub enum CompileSwitch {
+    // Compile with Tonedata
+    V,
+    // Write Tonedata after Compile
+    VW,
+    // Compile on OPN Mode (Default)
+    N,
+    // Compile on OPL Mode
+    L,
+    // Compile on OPM Mode
+    M,
+    // Compile on TOWNS Mode
+    T,
+    // Play after Compile Complete
+    P,
+    // Not Write Compiled File & Play
+    S,
+    // Not Set ADPCM_File before Play
+    A,
+    // Not Put Title Messages after Play
+    O,
+    // Calculate & Put Total Length of Parts
+    C,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ToneFormat {
+    #[default]
+    Opn,
+    Opl,
+    Opm,
+    Towns,
+}
+
+// options are merged in this order, the later one wins:
+//   1. environment variable MCOPT
+//   2. #Option
+//   3. command line
+// a switch is turned on by /X and off by -X
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CompileOptions {
+    pub tone_data: bool,
+    pub write_tone_data: bool,
+    pub tone_format: ToneFormat,
+    pub play: bool,
+    pub play_only: bool,
+    pub skip_pcm_file: bool,
+    pub quiet: bool,
+    pub total_length: bool,
+    pub target: TargetProfile,
+    // split notes and rests longer than 255 steps instead of reporting them
+    pub split_long_notes: bool,
+}
+
+impl CompileOptions {
+    pub fn new(target: TargetProfile) -> Self {
+        Self {
+            target,
+            ..Default::default()
+        }
+    }
+
+    pub fn set(&mut self, switch: CompileSwitch, on: bool) {
+        match switch {
+            CompileSwitch::V => {
+                self.tone_data = on;
+                // no tone data to write
+                self.write_tone_data &= on;
+            }
+            CompileSwitch::VW => {
+                self.tone_data |= on;
+                self.write_tone_data = on;
+            }
+            CompileSwitch::N => self.set_tone_format(ToneFormat::Opn, on),
+            CompileSwitch::L => self.set_tone_format(ToneFormat::Opl, on),
+            CompileSwitch::M => self.set_tone_format(ToneFormat::Opm, on),
+            CompileSwitch::T => self.set_tone_format(ToneFormat::Towns, on),
+            CompileSwitch::P => self.play = on,
+            CompileSwitch::S => self.play_only = on,
+            CompileSwitch::A => self.skip_pcm_file = on,
+            CompileSwitch::O => self.quiet = on,
+            CompileSwitch::C => self.total_length = on,
+        }
+    }
+
+    // turning off the current format goes back to the default
+    fn set_tone_format(&mut self, format: ToneFormat, on: bool) {
+        if on {
+            self.tone_format = format;
+        } else if self.tone_format == format {
+            self.tone_format = ToneFormat::default();
+        }
+    }
+
+    pub fn apply<S: AsRef<str>>(&mut self, code: &Code, values: &[S]) -> Vec<Diagnostic> {
+        let mut diagnostics = vec![];
+
+        for value in values {
+            let value = value.as_ref();
+            let (name, on) = match value.strip_prefix('-') {
+                Some(name) => (name, false),
+                None => (value, true),
+            };
+            match CompileSwitch::from_str(name) {
+                Ok(switch) => self.set(switch, on),
+                Err(_) => diagnostics.push(Diagnostic::error(
+                    code,
+                    None,
+                    SemanticError::InvalidOption(value.to_string()),
+                )),
+            }
+        }
+
+        diagnostics
+    }
+
+    pub fn apply_str(&mut self, code: &Code, options: &str) -> Vec<Diagnostic> {
+        self.apply(code, &Self::split(options))
+    }
+
+    pub fn apply_macros(&mut self, pass2: &Pass2Result) -> Vec<Diagnostic> {
+        pass2
+            .find_macros("Option")
+            .into_iter()
+            .map(|m| OptionMacro::from(m.clone()))
+            .flat_map(|m| self.apply(&m.code, &m.values))
+            .collect()
+    }
+
+    // command line arguments, e.g. ["/V", "/L"], ["/V/L"] or ["-V"]
+    pub fn apply_args<S: AsRef<str>>(&mut self, args: &[S]) -> Vec<Diagnostic> {
+        let code = Code {
+            file_name: "command line".to_string(),
+            ..Default::default()
+        };
+
+        args.iter()
+            .flat_map(|arg| self.apply_str(&code, arg.as_ref()))
+            .collect()
+    }
+
+    // `environment` is the value of MCOPT, read by the caller
+    pub fn merge<S: AsRef<str>>(
+        target: TargetProfile,
+        environment: Option<&str>,
+        pass2: &Pass2Result,
+        args: &[S],
+    ) -> (Self, Vec<Diagnostic>) {
+        let mut options = Self::new(target);
+        let mut diagnostics = vec![];
+
+        if let Some(environment) = environment {
+            diagnostics.extend(options.apply_str(&Self::environment_code(), environment));
+        }
+        diagnostics.extend(options.apply_macros(pass2));
+        diagnostics.extend(options.apply_args(args));
+
+        (options, diagnostics)
+    }
+
+    fn environment_code() -> Code {
+        Code {
+            file_name: OPTION_ENVIRONMENT.to_string(),
+            ..Default::default()
+        }
+    }
+
+    // "/V/L -C" into ["V", "L", "-C"]
+    fn split(options: &str) -> Vec<String> {
+        options
+            .replace('-', " -")
+            .split(['/', ' ', '\t'])
+            .filter(|e| !e.is_empty() && *e != "-")
+            .map(|e| e.to_string())
+            .collect::<Vec<String>>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{meta_models::VariantValue, models::Macro, target::TargetDriver};
+
+    use super::*;
+
+    fn option_macro(lines: usize, value: &str) -> Macro {
+        Macro {
+            code: Code {
+                file_name: "test.mml".to_string(),
+                lines,
+                chars: 0,
+            },
+            key: "option".to_string(),
+            value: VariantValue::String(value.to_string()),
+        }
+    }
+
+    #[test]
+    fn test_apply() {
+        let mut options = CompileOptions::default();
+        let diagnostics = options.apply_str(&Code::default(), "/v/L /o /c");
+
+        assert!(diagnostics.is_empty());
+        assert!(options.tone_data);
+        assert!(!options.write_tone_data);
+        assert_eq!(ToneFormat::Opl, options.tone_format);
+        assert!(options.quiet);
+        assert!(options.total_length);
+        assert!(!options.play);
+
+        let diagnostics = options.apply_str(&Code::default(), "/VW/N");
+        assert!(diagnostics.is_empty());
+        assert!(options.write_tone_data);
+        assert_eq!(ToneFormat::Opn, options.tone_format);
+    }
+
+    #[test]
+    fn test_apply_invalid() {
+        let mut options = CompileOptions::default();
+        let diagnostics = options.apply_str(&Code::default(), "/P /X /VV");
+
+        assert!(options.play);
+        assert_eq!(2, diagnostics.len());
+        assert!(diagnostics.iter().all(|d| d.is_error()));
+        assert_eq!(
+            SemanticError::InvalidOption("X".to_string()),
+            diagnostics[0].error
+        );
+        assert_eq!(
+            SemanticError::InvalidOption("VV".to_string()),
+            diagnostics[1].error
+        );
+    }
+
+    #[test]
+    fn test_merge_order() {
+        let mut pass2 = Pass2Result::default();
+        pass2.macros.push(option_macro(0, "/M /S"));
+        pass2.macros.push(option_macro(1, "/Z"));
+
+        let target = TargetProfile::new(TargetDriver::Pmd86);
+        let (options, diagnostics) =
+            CompileOptions::merge(target.clone(), Some("/L /A"), &pass2, &["/N"]);
+
+        // #Option overrides the environment, the command line overrides #Option
+        assert_eq!(ToneFormat::Opn, options.tone_format);
+        assert!(options.skip_pcm_file);
+        assert!(options.play_only);
+        assert_eq!(target, options.target);
+
+        assert_eq!(1, diagnostics.len());
+        assert_eq!("test.mml", diagnostics[0].code.file_name);
+        assert_eq!(1, diagnostics[0].code.lines);
+
+        let (options, _) = CompileOptions::merge::<&str>(target, Some("/L"), &pass2, &[]);
+        assert_eq!(ToneFormat::Opm, options.tone_format);
+    }
+
+    #[test]
+    fn test_merge_negation() {
+        let mut pass2 = Pass2Result::default();
+        pass2.macros.push(option_macro(0, "/P"));
+
+        let target = TargetProfile::new(TargetDriver::Pmd86);
+        let (options, diagnostics) =
+            CompileOptions::merge(target, Some("/C /VW /L"), &pass2, &["-C", "-P/-v", "-N"]);
+
+        // the command line clears the switches of MCOPT and #Option
+        assert!(diagnostics.is_empty());
+        assert!(!options.total_length);
+        assert!(!options.play);
+        assert!(!options.tone_data);
+        assert!(!options.write_tone_data);
+        // -N does not clear /L
+        assert_eq!(ToneFormat::Opl, options.tone_format);
+
+        let mut options = CompileOptions::default();
+        options.apply_str(&Code::default(), "/L-L /O");
+        assert_eq!(ToneFormat::Opn, options.tone_format);
+        assert!(options.quiet);
+        assert_eq!(
+            SemanticError::InvalidOption("-X".to_string()),
+            options.apply_str(&Code::default(), "-X")[0].error
+        );
+    }
+}
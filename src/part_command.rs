@@ -3,18 +3,23 @@ use std::str::FromStr;
 use crate::{
     commands::{
         commands_envelope::SsgPcmSoftwareEnvelope,
-        commands_loop::LocalLoop,
+        commands_lfo::{LfoDepthChange, LfoOption, PitchLfo, SoftwareLfo},
+        commands_compile_control::BarLine,
+        commands_loop::{LocalLoop, SongLoop},
         commands_mml::{
             DefaultLength, MasterTranspose, Note, NoteR, NoteX, Octave, OctaveReverse,
             OctaveUpDown, PartTranspose, Portamento, ProcessLastLengthAddSub,
             ProcessLastLengthMultiply, ProcessLastLengthUpdate, Quantize1, Quantize2, Slur,
-            TemporaryTranspose, Tie,
+            TemporaryTranspose, Tie, WholeLength,
         },
         commands_note_effect::Alpeggio,
-        commands_volume::Volume,
+        commands_pan::Pan,
+        commands_tempo::Tempo,
+        commands_tone::ToneNumber,
+        commands_volume::{RelativeVolume, Volume},
     },
     meta_models::{Code, MetaData, Pass2Working, Token, TokenStackTrait, TokenTrait},
-    models::{DivisorClock, NegativePositive},
+    models::{Chip, DivisorClock, NegativePositive},
     utils::get_type_name,
 };
 
@@ -75,6 +80,7 @@ macro_rules! try_from_get_some_value {
 //     };
 // }
 
+#[allow(unused_macros)]
 macro_rules! try_from_get_some_vec {
     ($expr:expr, $field:ident) => {
         match $expr {
@@ -157,6 +163,7 @@ impl TokenStackTrait<PartToken> for PartTokenStack {
     }
 }
 
+#[allow(dead_code)]
 impl PartTokenStack {
     pub fn part_command_stack(&self) -> &PartCommandStack {
         &self.part_command_stack
@@ -178,7 +185,7 @@ impl PartTokenStack {
     }
 
     pub fn dequeue(&mut self) -> Option<PartToken> {
-        if self.stack.len() > 0 {
+        if !self.stack.is_empty() {
             return Some(self.stack.remove(0));
         }
 
@@ -199,10 +206,7 @@ impl PartTokenStack {
             panic!("get_by_state({})", state);
         }
 
-        match r.get(0) {
-            Some(e) => Some(*e),
-            None => None,
-        }
+        r.first().copied()
     }
 
     pub fn get_and_cast<T>(&self, state: State) -> Result<Option<T>, <T as FromStr>::Err>
@@ -232,7 +236,7 @@ impl PartTokenStack {
             panic!("pop_by_state({}): len > 1", state);
         }
 
-        match r.get(0) {
+        match r.first() {
             Some(e) => {
                 let t = self
                     .stack
@@ -298,6 +302,7 @@ pub trait PartCommandStruct: std::fmt::Debug {
     fn to_variant(self) -> PartCommand;
 
     fn is_block() -> bool;
+    #[allow(dead_code)]
     fn is_match(command: &str) -> bool;
     fn parse(working: &mut Pass2Working, c: char) -> PartCommandParseState;
 }
@@ -338,6 +343,7 @@ impl PartCommandStack {
         self.stack.last_mut().unwrap().push(token);
     }
 
+    #[allow(dead_code)]
     pub fn pop_token(&mut self) -> Option<WrappedPartCommand> {
         self.stack.last_mut().unwrap().pop()
     }
@@ -361,6 +367,7 @@ pub enum PartCommand {
     PartOctaveChangeNegative(Octave),
 
     DefaultLength(DefaultLength),
+    WholeLength(WholeLength),
 
     ProcessLastLengthUpdate(ProcessLastLengthUpdate),
     ProcessLastLengthAdd(ProcessLastLengthAddSub),
@@ -377,33 +384,217 @@ pub enum PartCommand {
     PartTranspose(PartTranspose),
     MasterTranspose(MasterTranspose),
 
+    ToneNumber(ToneNumber),
+
+    SongLoop(SongLoop),
     LocalLoop(LocalLoop),
 
     SsgPcmSoftwareEnvelope(SsgPcmSoftwareEnvelope),
 
+    SoftwareLfoA(SoftwareLfo),
+    SoftwareLfoB(SoftwareLfo),
+    LfoWaveformA(LfoOption),
+    LfoWaveformB(LfoOption),
+    LfoSpeedModeA(LfoOption),
+    LfoSpeedModeB(LfoOption),
+    LfoSlotMaskA(LfoOption),
+    LfoSlotMaskB(LfoOption),
+    PitchLfoA(PitchLfo),
+    PitchLfoB(PitchLfo),
+    LfoDepthChangeA(LfoDepthChange),
+    LfoDepthChangeB(LfoDepthChange),
+
     Volume1(Volume),
     Volume2(Volume),
     GlobalVolume1Positive(Volume),
     GlobalVolume1Negative(Volume),
     GlobalVolume2Positive(Volume),
     GlobalVolume2Negative(Volume),
+    RelativeVolumeUp(RelativeVolume),
+    RelativeVolumeDown(RelativeVolume),
+
+    Tempo1(Tempo),
+    Tempo2(Tempo),
 
     Alpeggio(Alpeggio),
+
+    Pan(Pan),
+    PanEx(Pan),
+
+    BarLine(BarLine),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Availability {
+    Supported,
+    Differs,
+    Unsupported,
 }
 
+const ALL_CHIPS: &[Chip] = &[
+    Chip::Fm,
+    Chip::Ssg,
+    Chip::Pcm,
+    Chip::RhythmSelect,
+    Chip::RhythmDefine,
+];
+const TONAL_CHIPS: &[Chip] = &[Chip::Fm, Chip::Ssg, Chip::Pcm];
+const TONAL_RHYTHM_DEFINE_CHIPS: &[Chip] = &[Chip::Fm, Chip::Ssg, Chip::Pcm, Chip::RhythmDefine];
+
+impl PartCommand {
+    pub fn command_name(&self) -> String {
+        match self {
+            PartCommand::Nop => "".to_string(),
+            PartCommand::Note(c) => c.command.clone(),
+            PartCommand::NoteX(c) => c.command.clone(),
+            PartCommand::NoteR(c) => c.command.clone(),
+            PartCommand::Portamento(c) => c.begin_command.clone(),
+            PartCommand::Octave(c)
+            | PartCommand::PartOctaveChangePositive(c)
+            | PartCommand::PartOctaveChangeNegative(c) => c.command.clone(),
+            PartCommand::OctaveUp(c) | PartCommand::OctaveDown(c) => c.command.clone(),
+            PartCommand::OctaveReverse(c) => c.command.clone(),
+            PartCommand::DefaultLength(c) => c.command.clone(),
+            PartCommand::WholeLength(c) => c.command.clone(),
+            PartCommand::ProcessLastLengthUpdate(c) => c.command.clone(),
+            PartCommand::ProcessLastLengthAdd(c) | PartCommand::ProcessLastLengthSubtract(c) => {
+                c.command.clone()
+            }
+            PartCommand::ProcessLastLengthMultiply(c) => c.command.clone(),
+            PartCommand::Tie(c) => c.command.clone(),
+            PartCommand::Slur(c) => c.command.clone(),
+            PartCommand::Quantize1(c) => c.command.clone(),
+            PartCommand::Quantize2(c) => c.command.clone(),
+            PartCommand::AbsoluteTranspose(c) | PartCommand::RelativeTranspose(c) => {
+                c.command.clone()
+            }
+            PartCommand::PartTranspose(c) => c.command_begin.clone(),
+            PartCommand::MasterTranspose(c) => c.command.clone(),
+            PartCommand::ToneNumber(c) => c.command.clone(),
+            PartCommand::SongLoop(c) => c.command.clone(),
+            PartCommand::LocalLoop(c) => c.begin_command.clone(),
+            PartCommand::SsgPcmSoftwareEnvelope(c) => c.command.clone(),
+            PartCommand::SoftwareLfoA(c) | PartCommand::SoftwareLfoB(c) => c.command.clone(),
+            PartCommand::LfoWaveformA(c)
+            | PartCommand::LfoWaveformB(c)
+            | PartCommand::LfoSpeedModeA(c)
+            | PartCommand::LfoSpeedModeB(c)
+            | PartCommand::LfoSlotMaskA(c)
+            | PartCommand::LfoSlotMaskB(c) => c.command.clone(),
+            PartCommand::PitchLfoA(c) | PartCommand::PitchLfoB(c) => c.command.clone(),
+            PartCommand::LfoDepthChangeA(c) | PartCommand::LfoDepthChangeB(c) => c.command.clone(),
+            PartCommand::Volume1(c)
+            | PartCommand::Volume2(c)
+            | PartCommand::GlobalVolume1Positive(c)
+            | PartCommand::GlobalVolume1Negative(c)
+            | PartCommand::GlobalVolume2Positive(c)
+            | PartCommand::GlobalVolume2Negative(c) => c.command.clone(),
+            PartCommand::RelativeVolumeUp(c) | PartCommand::RelativeVolumeDown(c) => {
+                c.command.clone()
+            }
+            PartCommand::Tempo1(c) | PartCommand::Tempo2(c) => c.command.clone(),
+            PartCommand::Alpeggio(c) => c.command_begin.clone(),
+            PartCommand::Pan(c) | PartCommand::PanEx(c) => c.command.clone(),
+            PartCommand::BarLine(c) => c.command.clone(),
+        }
+    }
+
+    // chips listed in the [音源] section of the command
+    pub fn chips(&self) -> &'static [Chip] {
+        match self {
+            PartCommand::Nop => ALL_CHIPS,
+
+            PartCommand::Note(_) => TONAL_RHYTHM_DEFINE_CHIPS,
+            PartCommand::NoteX(_) => TONAL_CHIPS,
+            PartCommand::NoteR(_) => ALL_CHIPS,
+            PartCommand::Portamento(_) => TONAL_CHIPS,
+
+            PartCommand::Octave(_)
+            | PartCommand::OctaveUp(_)
+            | PartCommand::OctaveDown(_)
+            | PartCommand::OctaveReverse(_)
+            | PartCommand::PartOctaveChangePositive(_)
+            | PartCommand::PartOctaveChangeNegative(_) => TONAL_CHIPS,
+
+            PartCommand::DefaultLength(_)
+            | PartCommand::WholeLength(_)
+            | PartCommand::ProcessLastLengthUpdate(_)
+            | PartCommand::ProcessLastLengthAdd(_)
+            | PartCommand::ProcessLastLengthSubtract(_)
+            | PartCommand::ProcessLastLengthMultiply(_) => ALL_CHIPS,
+
+            PartCommand::Tie(_) | PartCommand::Slur(_) => TONAL_CHIPS,
+
+            PartCommand::Quantize1(_) | PartCommand::Quantize2(_) => TONAL_RHYTHM_DEFINE_CHIPS,
+
+            PartCommand::AbsoluteTranspose(_)
+            | PartCommand::RelativeTranspose(_)
+            | PartCommand::PartTranspose(_)
+            | PartCommand::MasterTranspose(_) => TONAL_CHIPS,
+
+            PartCommand::ToneNumber(_) => TONAL_RHYTHM_DEFINE_CHIPS,
+
+            PartCommand::SongLoop(_) => ALL_CHIPS,
+            PartCommand::LocalLoop(_) => ALL_CHIPS,
+
+            PartCommand::SsgPcmSoftwareEnvelope(_) => &[Chip::Ssg, Chip::Pcm],
+
+            PartCommand::SoftwareLfoA(_)
+            | PartCommand::SoftwareLfoB(_)
+            | PartCommand::LfoWaveformA(_)
+            | PartCommand::LfoWaveformB(_)
+            | PartCommand::LfoSpeedModeA(_)
+            | PartCommand::LfoSpeedModeB(_)
+            | PartCommand::PitchLfoA(_)
+            | PartCommand::PitchLfoB(_)
+            | PartCommand::LfoDepthChangeA(_)
+            | PartCommand::LfoDepthChangeB(_) => TONAL_CHIPS,
+
+            PartCommand::LfoSlotMaskA(_) | PartCommand::LfoSlotMaskB(_) => &[Chip::Fm],
+
+            PartCommand::Volume1(_)
+            | PartCommand::Volume2(_)
+            | PartCommand::GlobalVolume1Positive(_)
+            | PartCommand::GlobalVolume1Negative(_)
+            | PartCommand::GlobalVolume2Positive(_)
+            | PartCommand::GlobalVolume2Negative(_)
+            | PartCommand::RelativeVolumeUp(_)
+            | PartCommand::RelativeVolumeDown(_) => TONAL_RHYTHM_DEFINE_CHIPS,
+
+            PartCommand::Tempo1(_) | PartCommand::Tempo2(_) => ALL_CHIPS,
+
+            PartCommand::Alpeggio(_) => TONAL_CHIPS,
+
+            PartCommand::Pan(_) | PartCommand::PanEx(_) => &[Chip::Fm, Chip::Pcm],
+            PartCommand::BarLine(_) => ALL_CHIPS,
+        }
+    }
+
+    pub fn availability(&self, chip: &Chip) -> Availability {
+        if self.chips().contains(chip) {
+            return Availability::Supported;
+        }
+
+        match (self, chip) {
+            // E on FM parts drives TL instead of the SSG/PCM volume
+            (PartCommand::SsgPcmSoftwareEnvelope(_), Chip::Fm) => Availability::Differs,
+            _ => Availability::Unsupported,
+        }
+    }
+}
+
+#[allow(dead_code)]
 pub trait IsPartCommand {}
 impl IsPartCommand for PartCommand {}
 
 pub type WrappedPartCommand = MetaData<PartCommand>;
 
 pub(crate) fn to_some_i8(sign: Option<NegativePositive>, value: Option<u8>) -> Option<i8> {
-    if value.is_none() {
-        return None;
-    }
+    let value = value?;
 
     Some(match sign {
-        Some(NegativePositive::Positive) | None => value.unwrap() as i8,
-        Some(NegativePositive::Negative) => -(value.unwrap() as i8),
+        Some(NegativePositive::Positive) | None => value as i8,
+        Some(NegativePositive::Negative) => -(value as i8),
     })
 }
 
@@ -416,7 +607,7 @@ pub(crate) fn count_dots(dots: Option<String>) -> u8 {
 }
 
 pub(crate) fn make_some_length(length_vec: Vec<PartToken>) -> Option<DivisorClock<u8>> {
-    if length_vec.len() > 0 {
+    if !length_vec.is_empty() {
         Some(DivisorClock::try_from(length_vec).unwrap())
     } else {
         None
@@ -425,8 +616,6 @@ pub(crate) fn make_some_length(length_vec: Vec<PartToken>) -> Option<DivisorCloc
 
 #[cfg(test)]
 mod tests {
-    use super::*;
-
     #[test]
     fn test_note_1() {}
 }
@@ -33,20 +33,14 @@ impl ParseUtil for Pass1 {
             '`' => {
                 return Command::Comment2(self.get_code().clone());
             }
-            '@' => {
-                if self.get_code().chars == 0 {
-                    return Command::FmToneDefine(self.get_code().clone());
-                }
+            '@' if self.get_code().chars == 0 => {
+                return Command::FmToneDefine(self.get_code().clone());
             }
-            '#' => {
-                if self.get_code().chars == 0 {
-                    return Command::Macro(self.get_code().clone());
-                }
+            '#' if self.get_code().chars == 0 => {
+                return Command::Macro(self.get_code().clone());
             }
-            '!' => {
-                if self.get_code().chars == 0 {
-                    return Command::Variable(self.get_code().clone());
-                }
+            '!' if self.get_code().chars == 0 => {
+                return Command::Variable(self.get_code().clone());
             }
             _ => {}
         };
@@ -149,7 +143,7 @@ impl Pass1 {
                         break 'macro_command;
                     }
 
-                    if tokens.len() <= 0 {
+                    if tokens.len() == 0 {
                         tokens.push(&token);
                         token.clear();
                         break 'macro_command;
@@ -181,7 +175,7 @@ impl Pass1 {
                         break 'variable_command;
                     }
 
-                    if tokens.len() <= 0 {
+                    if tokens.len() == 0 {
                         tokens.push(&token);
                         token.clear();
                         break 'variable_command;
@@ -283,15 +277,15 @@ impl Pass1 {
                     return Err(Pass1Error::ParseError(self.code.lines, self.code.chars));
                 };
 
-                return Ok(FmToneDefine {
+                Ok(FmToneDefine {
                     code: self.code.clone(),
                     tone_number,
                     algorism,
                     feedback,
                     name,
-                });
+                })
             }
-            _ => return Err(Pass1Error::ParseError(self.code.lines, self.code.chars)),
+            _ => Err(Pass1Error::ParseError(self.code.lines, self.code.chars)),
         }
     }
 
@@ -386,12 +380,12 @@ mod tests {
         assert_eq!(18, result.comment1s.len());
         assert_eq!(0, result.comment2s.len());
 
-        assert_eq!(0, result.fm_tones.get(0).unwrap().tone_number);
-        assert_eq!(7, result.fm_tones.get(0).unwrap().algorism);
-        assert_eq!(0, result.fm_tones.get(0).unwrap().feedback);
+        assert_eq!(0, result.fm_tones.first().unwrap().tone_number);
+        assert_eq!(7, result.fm_tones.first().unwrap().algorism);
+        assert_eq!(0, result.fm_tones.first().unwrap().feedback);
         assert_eq!(
             &"SSG-EG1".to_owned(),
-            result.fm_tones.get(0).unwrap().name.as_ref().unwrap()
+            result.fm_tones.first().unwrap().name.as_ref().unwrap()
         );
 
         assert_eq!(1, result.fm_tones.get(1).unwrap().tone_number);
@@ -410,35 +404,38 @@ mod tests {
             result.fm_tones.get(2).unwrap().name.as_ref().unwrap()
         );
 
-        assert_eq!("h", result.variables.get(0).unwrap().name);
-        assert_eq!("E1,-2,1,0v12P3w0q4", result.variables.get(0).unwrap().value);
+        assert_eq!("h", result.variables.first().unwrap().name);
+        assert_eq!(
+            "E1,-2,1,0v12P3w0q4",
+            result.variables.first().unwrap().value
+        );
         assert_eq!("o", result.variables.get(1).unwrap().name);
         assert_eq!("E1,-1,4,0v13P3w0q0", result.variables.get(1).unwrap().value);
         assert_eq!("s", result.variables.get(2).unwrap().name);
         assert_eq!("E2,-1,2,0v13P2w8q0", result.variables.get(2).unwrap().value);
 
-        assert_eq!("Title", result.macros.get(0).unwrap().key);
-        if let VariantValue::String(title) = &result.macros.get(0).unwrap().value {
+        assert_eq!("Title", result.macros.first().unwrap().key);
+        if let VariantValue::String(title) = &result.macros.first().unwrap().value {
             assert_eq!("PMD ver4.8s SSG-EG Sample", title);
         } else {
-            assert!(false);
+            panic!("unexpected value");
         }
 
         assert_eq!("Composer", result.macros.get(1).unwrap().key);
         if let VariantValue::String(composer) = &result.macros.get(1).unwrap().value {
             assert_eq!("M.Kajihara", composer);
         } else {
-            assert!(false);
+            panic!("unexpected value");
         }
 
         assert_eq!("Memo", result.macros.get(2).unwrap().key);
         if let VariantValue::String(arranger) = &result.macros.get(2).unwrap().value {
             assert_eq!("emulatorではいろいろと厳しいかもしれません", arranger);
         } else {
-            assert!(false);
+            panic!("unexpected value");
         }
 
-        assert_eq!(" nm alg fbl", result.comment1s.get(0).unwrap().comment);
+        assert_eq!(" nm alg fbl", result.comment1s.first().unwrap().comment);
         assert_eq!(
             " ar  dr  sr  rr  sl  tl  ks  ml  dt ams   seg",
             result.comment1s.get(1).unwrap().comment
@@ -1,24 +1,31 @@
-use std::{f32::consts::E, str::FromStr, sync::Arc};
+use std::{collections::HashMap, str::FromStr};
 
 use crate::{
     commands::{
+        commands_compile_control::BarLine,
         commands_envelope::SsgPcmSoftwareEnvelope,
-        commands_loop::LocalLoop,
+        commands_lfo::{LfoDepthChange, LfoOption, PitchLfo, SoftwareLfo},
+        commands_loop::{DEFAULT_LOOP_COUNT, LocalLoop, SongLoop},
         commands_mml::{
-            DefaultLength, MasterTranspose, Note, Octave, OctaveUpDown, PartTranspose, Portamento,
-            Quantize1, Quantize2, TemporaryTranspose,
+            DefaultLength, MasterTranspose, Note, NoteR, Octave, OctaveUpDown, PartTranspose,
+            Portamento, ProcessLastLengthAddSub, ProcessLastLengthMultiply,
+            ProcessLastLengthUpdate, Quantize1, Quantize2, Slur, TemporaryTranspose, Tie,
+            WholeLength,
         },
         commands_note_effect::Alpeggio,
-        commands_volume::Volume,
+        commands_pan::Pan,
+        commands_tempo::Tempo,
+        commands_tone::ToneNumber,
+        commands_volume::{RelativeVolume, Volume},
     },
     errors::Pass2Error,
+    loop_check::check_loops,
     meta_models::{
         Code, Command, Pass1Result, Pass2Result, Pass2Working, TokenStackTrait, TokenTrait,
     },
-    models::PartSymbol,
+    models::{ExtendPartSymbol, PartSymbol},
     part_command::{
-        PartCommand, PartCommandParseState, PartCommandStruct, PartToken, PartTokenStack,
-        WrappedPartCommand,
+        PartCommand, PartCommandParseState, PartCommandStruct, PartTokenStack, WrappedPartCommand,
     },
     utils::{ParseUtil, get_type_name, is_n, is_sep},
 };
@@ -51,35 +58,22 @@ impl ParseUtil for Pass2 {
             '`' => {
                 return Command::Comment2(self.clone_code());
             }
-            '@' => {
-                if self.get_code().chars == 0 {
-                    return Command::FmToneDefine(self.clone_code());
-                }
+            '@' if self.get_code().chars == 0 => {
+                return Command::FmToneDefine(self.clone_code());
             }
-            '#' => {
-                if self.get_code().chars == 0 {
-                    return Command::Macro(self.clone_code());
-                }
+            '#' if self.get_code().chars == 0 => {
+                return Command::Macro(self.clone_code());
             }
-            '!' => {
-                if self.get_code().chars == 0 {
-                    return Command::Variable(self.clone_code());
-                }
+            '!' if self.get_code().chars == 0 => {
+                return Command::Variable(self.clone_code());
             }
-            'A'..'Z' => {
-                if self.get_code().chars == 0 {
-                    return Command::Part(
-                        self.clone_code(),
-                        PartSymbol::from_str(&c.to_string().as_str()).unwrap(),
-                    );
+            'A'..='Z' | 'a'..='z' if self.get_code().chars == 0 => {
+                if let Ok(part) = PartSymbol::from_str(&c.to_string()) {
+                    return Command::Part(self.clone_code(), part);
                 }
-            }
-            'a'..'z' => {
-                if self.get_code().chars == 0 {
-                    return Command::Part(
-                        self.clone_code(),
-                        PartSymbol::from_str(&c.to_string().as_str()).unwrap(),
-                    );
+
+                if let Ok(part) = ExtendPartSymbol::from_str(&c.to_string()) {
+                    return Command::ExtendPart(self.clone_code(), part);
                 }
             }
             _ => {
@@ -87,7 +81,7 @@ impl ParseUtil for Pass2 {
             }
         }
 
-        return Command::Nop;
+        Command::Nop
     }
 }
 
@@ -101,13 +95,25 @@ impl Pass2 {
     }
 
     pub fn parse(&mut self) -> Result<Pass2Result, Pass2Error> {
-        let mut result = Pass2Result::default();
+        let mut result = Pass2Result {
+            macros: self.pass1.macros.clone(),
+            variables: self.pass1.variables.clone(),
+            fm_tones: self.pass1.fm_tones.clone(),
+            comment1s: self.pass1.comment1s.clone(),
+            comment2s: self.pass1.comment2s.clone(),
+            ..Default::default()
+        };
 
-        let mut working = Pass2Working::default();
+        let (mml, diagnostics) = check_loops(&self.code, &self.mml);
+        result.diagnostics = diagnostics;
+
+        // a loop may continue in the next line of its part
+        let mut workings: HashMap<char, Pass2Working> = HashMap::new();
+        let mut symbol = ' ';
 
         let mut command = Command::Nop;
 
-        let new_lined_mml = format!("{}\n", self.mml);
+        let new_lined_mml = format!("{}\n", mml);
         let mut chars = new_lined_mml.chars();
         let mut maybe_c = chars.next();
         while maybe_c.is_some() {
@@ -116,6 +122,7 @@ impl Pass2 {
             match command {
                 Command::Nop => 'nop: {
                     command = self.parse_command(c);
+                    symbol = c;
                     break 'nop;
                 }
                 Command::Comment1(_) => 'comment1_command: {
@@ -132,57 +139,18 @@ impl Pass2 {
 
                     command = Command::Nop;
                 }
-                Command::Part(_, ref part) => 'part_command: {
-                    // let w = WrappedPartCommand::new(self.get_code(), part_command.clone());
-                    // commands.push(w);
-                    working.code = self.code.clone();
-                    let res = self.parse_part_command(&mut working, c);
-                    if is_n(c) {
-                        if !working.token.is_empty() {
-                            working.push();
-                        }
-
-                        // println!("end ==> working.tokens = {:?}", working.tokens);
-
-                        working.clear();
-
-                        if working.part_command_stack.stack().len() > 0 {
-                            let mut tmp = vec![];
-                            while working.part_command_stack.stack_mut().last().is_some() {
-                                if let Some(s) = working.part_command_stack.stack_mut().pop() {
-                                    tmp.push(s);
-                                } else {
-                                    break;
-                                }
-                            }
-
-                            while tmp.last().is_some() {
-                                if let Some(s) = tmp.pop() {
-                                    working.commands.extend(s);
-                                }
-                            }
-                        }
-
-                        result.parts.push((part.clone(), working.commands.clone()));
-                        println!("result.parts: {:?}", result.parts);
+                Command::Part(_, ref part) => {
+                    let working = workings.entry(symbol).or_default();
+                    if let Some(commands) = self.parse_part_line(working, c) {
+                        let commands = Self::limit_parts(symbol, commands);
+                        result.parts.push((part.clone(), commands));
                     }
-
-                    if let Ok(r) = res {
-                        // match r {
-                        //     PartCommand::Nop => {
-                        //         break 'part_command;
-                        //     }
-                        //     _ => {
-                        //         working.clear();
-                        //         commands.push(WrappedPartCommand::new(self.get_code(), r));
-                        //         if is_n(c) {
-                        //             result.parts.push((part.clone(), commands.clone()));
-                        //             commands.clear();
-                        //         }
-
-                        //         command = Command::Nop;
-                        //     }
-                        // };
+                }
+                Command::ExtendPart(_, ref part) => {
+                    let working = workings.entry(symbol).or_default();
+                    if let Some(commands) = self.parse_part_line(working, c) {
+                        let commands = Self::limit_parts(symbol, commands);
+                        result.extend_parts.push((part.clone(), commands));
                     }
                 }
                 _ => {
@@ -198,11 +166,54 @@ impl Pass2 {
             }
         }
 
-        println!("Result: {:?}", working.commands);
+        Self::apply_loop_default(&mut result);
 
         Ok(result)
     }
 
+    // feeds a char of a part line, the commands of the line when it ends
+    // with no loop open. the commands of an open loop wait for its ]
+    fn parse_part_line(
+        &self,
+        working: &mut Pass2Working,
+        c: char,
+    ) -> Option<Vec<WrappedPartCommand>> {
+        working.code = self.code.clone();
+        let _ = self.parse_part_command(working, c);
+        if !is_n(c) {
+            return None;
+        }
+
+        if !working.token.is_empty() {
+            working.push();
+        }
+
+        working.clear();
+
+        if working.loop_nest > 0 {
+            return None;
+        }
+
+        if !working.part_command_stack.stack().is_empty() {
+            let mut tmp = vec![];
+            while working.part_command_stack.stack_mut().last().is_some() {
+                if let Some(s) = working.part_command_stack.stack_mut().pop() {
+                    tmp.push(s);
+                } else {
+                    break;
+                }
+            }
+
+            while tmp.last().is_some() {
+                if let Some(s) = tmp.pop() {
+                    working.commands.extend(s);
+                }
+            }
+        }
+
+        Some(std::mem::take(&mut working.commands))
+    }
+
     fn parse_part_command(
         &self,
         working: &mut Pass2Working,
@@ -227,15 +238,15 @@ impl Pass2 {
 
             let t = working.token.chars().as_str();
             match t {
-                "c" | "d" | "e" | "f" | "g" | "a" | "b" | "q" | "Q" | "l" | "<" | ">" | "V"
-                | "E" => {
+                "c" | "d" | "e" | "f" | "g" | "a" | "b" | "r" | "q" | "Q" | "C" | "<" | ">"
+                | "E" | "L" | "t" | "T" | "|" | "(" | ")" | "^" => {
                     working.push();
                     working.jump(1);
                     return Ok(PartCommand::Nop);
                 }
                 // complex commands
                 "o" => {
-                    if working.state <= 0 {
+                    if working.state == 0 {
                         working.jump(1);
                         return Ok(PartCommand::Nop);
                     }
@@ -252,8 +263,30 @@ impl Pass2 {
                         }
                     }
                 }
+                "l" => {
+                    if working.state == 0 {
+                        working.jump(1);
+                        return Ok(PartCommand::Nop);
+                    }
+
+                    // l= l+ l- l^ and l keep the command at state 0 as the notes
+                    working.jump(0);
+                    match c {
+                        '=' | '+' | '-' | '^' => {
+                            working.eat(c);
+                            working.push();
+                            working.jump(1);
+                            return Ok(PartCommand::Nop);
+                        }
+                        _ => {
+                            working.push();
+                            working.jump(1);
+                            // fall
+                        }
+                    }
+                }
                 "_" => {
-                    if working.state <= 0 {
+                    if working.state == 0 {
                         working.jump(1);
                         return Ok(PartCommand::Nop);
                     }
@@ -272,14 +305,13 @@ impl Pass2 {
                     }
                 }
                 "{" => {
-                    if working.state <= 0 {
+                    if working.state == 0 {
                         working.jump(1);
                         return Ok(PartCommand::Nop);
                     }
 
                     match c {
                         '{' => {
-                            println!("{{{{");
                             working.eat(c);
                             working.push();
 
@@ -289,22 +321,17 @@ impl Pass2 {
                             return Ok(PartCommand::Nop);
                         }
                         _ => {
-                            working.jump(3);
+                            // portamento, "{" waits on the stack for "}"
+                            working.jump(0);
                             working.push();
-                            let mut tokens = working.tokens.clone();
-
-                            working.jump(3);
                             Self::quick_save(working);
 
-                            // push "{"
-                            working.tokens.push(&tokens.pop().unwrap());
-
-                            // fall
+                            return self.parse_part_command(working, c);
                         }
                     }
                 }
                 "}" => {
-                    if working.state <= 0 {
+                    if working.state == 0 {
                         working.jump(1);
                         return Ok(PartCommand::Nop);
                     }
@@ -331,10 +358,18 @@ impl Pass2 {
                             let mut tokens = working.tokens.clone();
 
                             Self::quick_load(working);
-                            working.jump(3);
+                            // "}" of portamento follows "{" at state 1, of "_{" at 3
+                            let is_portamento = working
+                                .tokens
+                                .first()
+                                .is_some_and(|t| t.chars().as_str() == "{");
+                            let state = if is_portamento { 1 } else { 3 };
+                            working.jump(state);
 
                             // push "}"
-                            working.tokens.push(&tokens.pop().unwrap());
+                            let mut token = tokens.pop().unwrap();
+                            token.set_state(state);
+                            working.tokens.push(&token);
 
                             // fall
                         }
@@ -353,7 +388,7 @@ impl Pass2 {
                     return Ok(PartCommand::Nop);
                 }
                 "]" => {
-                    working.loop_nest -= 1;
+                    working.loop_nest = working.loop_nest.saturating_sub(1);
 
                     Self::quick_load(working);
                     working.jump(5);
@@ -361,23 +396,23 @@ impl Pass2 {
                     // fall through
                 }
                 ":" => {
-                    // determine before token
-                    working.tokens = if let Some(v) = working.tokens_stack.pop() {
-                        // prepare stack for post part commands
-                        working.part_command_stack.init_vec();
-                        v
-                    } else {
-                        panic!("LocalLoop: invalid stack");
-                    };
-                    working.state = working.state_stack.pop().unwrap();
-                    working.jump(3);
+                    // body_pre is kept by the tokens of [, body_post follows in a new vec
+                    if let (Some(tokens), Some(body_pre)) = (
+                        working.tokens_stack.last_mut(),
+                        working.part_command_stack.pop_vec(),
+                    ) {
+                        working.token.set_code(&working.code);
+                        working.token.set_state(3);
+                        tokens.push(&working.token);
+                        tokens.part_command_stack_mut().push_vec(body_pre);
+                    }
 
+                    working.token.clear();
                     working.part_command_stack.init_vec();
-                    working.state_stack.push(working.state);
                     return Ok(PartCommand::Nop);
                 }
-                "v" => {
-                    if working.state <= 0 {
+                "v" | "V" => {
+                    if working.state == 0 {
                         working.jump(1);
                         return Ok(PartCommand::Nop);
                     }
@@ -394,6 +429,94 @@ impl Pass2 {
                         }
                     }
                 }
+                "M" => {
+                    if working.state == 0 {
+                        working.jump(1);
+                        return Ok(PartCommand::Nop);
+                    }
+
+                    match c {
+                        'A' | 'B' => {
+                            working.eat(c);
+                            working.push();
+                            return Ok(PartCommand::Nop);
+                        }
+                        'W' | 'X' | 'P' | 'D' | 'M' => {
+                            // MW, MX, MP, MD, MM may be followed by A or B
+                            working.eat(c);
+                            return Ok(PartCommand::Nop);
+                        }
+                        _ => {
+                            working.push();
+                            // fall
+                        }
+                    }
+                }
+                "MW" | "MX" | "MP" | "MD" | "MM" => match c {
+                    'A' | 'B' => {
+                        working.eat(c);
+                        working.push();
+                        return Ok(PartCommand::Nop);
+                    }
+                    _ => {
+                        working.push();
+                        // fall
+                    }
+                },
+                "@" => {
+                    if working.state == 0 {
+                        working.jump(1);
+                        return Ok(PartCommand::Nop);
+                    }
+
+                    match c {
+                        '@' => {
+                            working.eat(c);
+                            working.push();
+                            return Ok(PartCommand::Nop);
+                        }
+                        _ => {
+                            working.push();
+                            // fall
+                        }
+                    }
+                }
+                "&" => {
+                    if working.state == 0 {
+                        working.jump(1);
+                        return Ok(PartCommand::Nop);
+                    }
+
+                    match c {
+                        '&' => {
+                            working.eat(c);
+                            working.push();
+                            return Ok(PartCommand::Nop);
+                        }
+                        _ => {
+                            working.push();
+                            // fall
+                        }
+                    }
+                }
+                "p" => {
+                    if working.state == 0 {
+                        working.jump(1);
+                        return Ok(PartCommand::Nop);
+                    }
+
+                    match c {
+                        'x' => {
+                            working.eat(c);
+                            working.push();
+                            return Ok(PartCommand::Nop);
+                        }
+                        _ => {
+                            working.push();
+                            // fall
+                        }
+                    }
+                }
                 _ => {
                     panic!("unknwon command: {c}");
                 }
@@ -401,17 +524,21 @@ impl Pass2 {
         }
 
         let first_token = working.tokens.first().unwrap().chars().as_str();
-        println!(
-            "tokens ==> {:?} // first={first_token} // c={c}",
-            working.tokens
-        );
         match first_token {
             // 04: mml note
             "c" | "d" | "e" | "f" | "g" | "a" | "b" => {
                 self.__parse_part_command::<Note>(working, c)
             }
+            "r" => self.__parse_part_command::<NoteR>(working, c),
             "o" | "o+" | "o-" => self.__parse_part_command::<Octave>(working, c),
             "l" => self.__parse_part_command::<DefaultLength>(working, c),
+            "l=" => self.__parse_part_command::<ProcessLastLengthUpdate>(working, c),
+            "^" | "l+" | "l-" => self.__parse_part_command::<ProcessLastLengthAddSub>(working, c),
+            "l^" => self.__parse_part_command::<ProcessLastLengthMultiply>(working, c),
+            "&" => self.__parse_part_command::<Tie>(working, c),
+            "&&" => self.__parse_part_command::<Slur>(working, c),
+            "{" => self.__parse_part_command::<Portamento>(working, c),
+            "C" => self.__parse_part_command::<WholeLength>(working, c),
             "<" | ">" => self.__parse_part_command::<OctaveUpDown>(working, c),
             "_{" => self.__parse_part_command::<PartTranspose>(working, c),
             "_" | "__" => self.__parse_part_command::<TemporaryTranspose>(working, c),
@@ -422,25 +549,91 @@ impl Pass2 {
             "v" | "V" | "v+" | "v-" | "v)" | "v(" => {
                 self.__parse_part_command::<Volume>(working, c)
             }
+            ")" | "(" => self.__parse_part_command::<RelativeVolume>(working, c),
+            // 06: mml tone
+            "@" | "@@" => self.__parse_part_command::<ToneNumber>(working, c),
             // 08: mml envelope
             "E" => self.__parse_part_command::<SsgPcmSoftwareEnvelope>(working, c),
+            // 09: mml lfo
+            "M" | "MA" | "MB" => self.__parse_part_command::<SoftwareLfo>(working, c),
+            "MW" | "MWA" | "MWB" | "MX" | "MXA" | "MXB" | "MM" | "MMA" | "MMB" => {
+                self.__parse_part_command::<LfoOption>(working, c)
+            }
+            "MP" | "MPA" | "MPB" => self.__parse_part_command::<PitchLfo>(working, c),
+            "MD" | "MDA" | "MDB" => self.__parse_part_command::<LfoDepthChange>(working, c),
             // 10: mml loop
+            "L" => self.__parse_part_command::<SongLoop>(working, c),
             "[" => self.__parse_part_command::<LocalLoop>(working, c),
+            // 11: mml tempo
+            "t" | "T" => self.__parse_part_command::<Tempo>(working, c),
             // 12: mml note effect
             "{{" => self.__parse_part_command::<Alpeggio>(working, c),
+            // 13: mml pan
+            "p" | "px" => self.__parse_part_command::<Pan>(working, c),
+            // 16: mml compile control
+            "|" => self.__parse_part_command::<BarLine>(working, c),
             _ => {
                 panic!("unknown command: {first_token}");
             }
         }
     }
 
-    fn quick_save(working: &mut Pass2Working) {
-        println!("saving:");
-        println!("* token: {:?}", working.token);
-        println!("* tokens: {:?}", working.tokens);
-        println!("* tokens_stack: {:?}", working.tokens_stack);
-        println!("* pc_stack: {:?}", working.part_command_stack);
+    fn apply_loop_default(result: &mut Pass2Result) {
+        let loop_default = result
+            .find_macros("LoopDefault")
+            .last()
+            .and_then(|m| m.value.to_unsigned())
+            .unwrap_or(DEFAULT_LOOP_COUNT);
+
+        let parts = result.parts.iter_mut().map(|(_, commands)| commands);
+        let extend_parts = result.extend_parts.iter_mut().map(|(_, commands)| commands);
+        for commands in parts.chain(extend_parts) {
+            for command in commands.iter_mut() {
+                if let PartCommand::LocalLoop(l) = command.data_mut() {
+                    l.apply_loop_default(loop_default);
+                }
+            }
+        }
+    }
+
+    // drops the commands limited to other parts by |, up to the next |
+    fn limit_parts(symbol: char, commands: Vec<WrappedPartCommand>) -> Vec<WrappedPartCommand> {
+        let mut active = true;
+        Self::__limit_parts(symbol, commands, &mut active)
+    }
 
+    fn __limit_parts(
+        symbol: char,
+        commands: Vec<WrappedPartCommand>,
+        active: &mut bool,
+    ) -> Vec<WrappedPartCommand> {
+        let mut limited = vec![];
+        for mut command in commands {
+            let keep = match command.data_mut() {
+                PartCommand::BarLine(c) => {
+                    *active = c.is_active(symbol);
+                    true
+                }
+                PartCommand::LocalLoop(l) => {
+                    let keep = *active;
+                    let body_pre = std::mem::take(&mut l.body_pre);
+                    l.body_pre = Self::__limit_parts(symbol, body_pre, active);
+                    let body_post = std::mem::take(&mut l.body_post);
+                    l.body_post = Self::__limit_parts(symbol, body_post, active);
+                    keep
+                }
+                _ => *active,
+            };
+
+            if keep {
+                limited.push(command);
+            }
+        }
+
+        limited
+    }
+
+    fn quick_save(working: &mut Pass2Working) {
         working.switch_push_to_stack();
 
         working.part_command_stack.init_vec();
@@ -451,12 +644,6 @@ impl Pass2 {
         working.load_from_stack();
 
         working.switch_push_to_commands();
-
-        println!("loaded:");
-        println!("* token: {:?}", working.token);
-        println!("* tokens: {:?}", working.tokens);
-        println!("* tokens_stack: {:?}", working.tokens_stack);
-        println!("* pc_stack: {:?}", working.part_command_stack);
     }
 
     fn __parse_part_command<T>(
@@ -478,7 +665,7 @@ impl Pass2 {
             return self.parse_part_command(working, c);
         }
 
-        return Ok(PartCommand::Nop);
+        Ok(PartCommand::Nop)
     }
 
     fn push_part_command<T>(working: &mut Pass2Working)
@@ -505,7 +692,7 @@ impl Pass2 {
         //     working.commands.push(w);
         // }
 
-        if working.part_command_stack.stack().len() <= 0 {
+        if working.part_command_stack.stack().is_empty() {
             working.part_command_stack.init_vec();
         }
         working.part_command_stack.push_token(w);
@@ -562,7 +749,7 @@ mod tests {
 
         println!("{:?}", part_g_list);
 
-        let g_commands = part_g_list.get(0).unwrap();
+        let g_commands = part_g_list.first().unwrap();
         assert_eq!(12, g_commands.len());
 
         // c+4
@@ -573,7 +760,7 @@ mod tests {
             length: Some(DivisorClock::Divisor(4)),
             dots: 0,
         };
-        let actual = g_commands.get(0).unwrap();
+        let actual = g_commands.first().unwrap();
         assert_eq!(
             expected,
             if let PartCommand::Note(ref c) = *actual.data() {
@@ -746,6 +933,7 @@ mod tests {
             body_post: vec![],
             end_command: "]".to_string(),
             count: Some(8),
+            effective_count: 8,
         };
         let actual = g_commands.get(8).unwrap();
         assert_eq!(
@@ -883,4 +1071,92 @@ I	!c4!s[!h]3[!b[!h]3!s[!h]3]11 !sr!sr!sr !b!b!br !brr8 !br r1
 
         assert_eq!(7, result.get_parts(&PartSymbol::G).len());
     }
+
+    #[test]
+    fn test_loop() {
+        let mml = "A [c:d]3 [e[f]2]\nA [c d\nB c\nA e]2 c]2 [d";
+
+        let result = crate::parse("", mml).unwrap();
+
+        let parts = result.get_parts(&PartSymbol::A);
+        assert_eq!(2, parts.len());
+
+        let PartCommand::LocalLoop(l) = parts[0][0].data() else {
+            panic!("unexpected command: {:?}", parts[0][0]);
+        };
+        assert_eq!(Some(":".to_string()), l.separator);
+        assert_eq!(1, l.body_pre.len());
+        assert_eq!(1, l.body_post.len());
+        assert_eq!(Some(3), l.count);
+
+        let PartCommand::LocalLoop(l) = parts[0][1].data() else {
+            panic!("unexpected command: {:?}", parts[0][1]);
+        };
+        assert!(matches!(l.body_pre[1].data(), PartCommand::LocalLoop(_)));
+        assert_eq!(None, l.count);
+        assert_eq!(DEFAULT_LOOP_COUNT, l.effective_count);
+
+        // the loop over the lines is a single loop
+        assert_eq!(3, parts[1].len());
+        let PartCommand::LocalLoop(l) = parts[1][0].data() else {
+            panic!("unexpected command: {:?}", parts[1][0]);
+        };
+        assert_eq!(3, l.body_pre.len());
+        assert_eq!(Some(2), l.count);
+        assert_eq!(1, result.get_parts(&PartSymbol::B).len());
+
+        // the broken brackets are skipped
+        assert_eq!(2, result.diagnostics.len());
+        assert_eq!(3, result.diagnostics[0].code.lines);
+        assert_eq!(7, result.diagnostics[0].code.chars);
+        assert_eq!(10, result.diagnostics[1].code.chars);
+    }
+
+    #[test]
+    fn test_bar_line_parts() {
+        let mml = "A c |AB d |B e | f |\nB c |AB d |B e | f";
+
+        let result = crate::parse("", mml).unwrap();
+
+        let notes = |part: &PartSymbol| {
+            result.get_parts(part)[0]
+                .iter()
+                .filter_map(|c| match c.data() {
+                    PartCommand::Note(n) => Some(n.command.clone()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(vec!["c", "d", "f"], notes(&PartSymbol::A));
+        assert_eq!(vec!["c", "d", "e", "f"], notes(&PartSymbol::B));
+
+        let PartCommand::BarLine(b) = result.get_parts(&PartSymbol::A)[0][1].data() else {
+            panic!("unexpected command");
+        };
+        assert_eq!(Some("AB".to_string()), b.parts);
+    }
+
+    #[test]
+    fn test_loop_default() {
+        let mml = "#LoopDefault 3\nA [c]4 [[d]]";
+
+        let result = crate::parse("", mml).unwrap();
+
+        let commands = result.get_parts(&PartSymbol::A)[0];
+        let PartCommand::LocalLoop(l) = commands[0].data() else {
+            panic!("unexpected command: {:?}", commands[0]);
+        };
+        assert_eq!(4, l.effective_count);
+
+        let PartCommand::LocalLoop(l) = commands[1].data() else {
+            panic!("unexpected command: {:?}", commands[1]);
+        };
+        assert_eq!(None, l.count);
+        assert_eq!(3, l.effective_count);
+
+        let PartCommand::LocalLoop(l) = l.body_pre[0].data() else {
+            panic!("unexpected command: {:?}", l.body_pre[0]);
+        };
+        assert_eq!(3, l.effective_count);
+    }
 }
@@ -0,0 +1,424 @@
+use std::str::FromStr;
+
+use crate::{
+    alpeggio,
+    commands::commands_mml::{Note, PartTranspose},
+    diagnostics::Diagnostic,
+    errors::SemanticError,
+    events::{Event, EventKind, PartEvents},
+    meta_models::{Code, Pass2Result},
+    models::{
+        Chip, NegativePositive, NegativePositiveEqual, NoteCommand, OctaveMacro, PartSymbol,
+        ReverseNormalOption, TransposeMacro,
+    },
+    part_command::PartCommand,
+    portamento::PortamentoSlide,
+};
+
+// MIDI note number, o4c = 60
+pub type Pitch = i16;
+
+pub const DEFAULT_OCTAVE: i8 = 4;
+pub const MIN_OCTAVE: i8 = 1;
+pub const MAX_OCTAVE: i8 = 8;
+
+pub fn to_pitch(octave: i8, semitone: i16) -> Pitch {
+    (octave as Pitch + 1) * 12 + semitone
+}
+
+pub fn octave_of(pitch: Pitch) -> i8 {
+    (pitch.div_euclid(12) - 1) as i8
+}
+
+fn signed(sign: &Option<NegativePositive>, value: u8) -> i16 {
+    match sign {
+        Some(NegativePositive::Negative) => -(value as i16),
+        Some(NegativePositive::Positive) | None => value as i16,
+    }
+}
+
+// compile time state of pitches, follows the MML text order
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PitchState {
+    pub octave: i8,
+    // o+ o-, added to the following o
+    pub part_octave: i8,
+    // X, #Octave Reverse
+    pub reverse: bool,
+    // _ __
+    pub transpose: i16,
+    // _M, #Transpose
+    pub master_transpose: i16,
+    // _{ }, indexed by c d e f g a b
+    pub key_signature: [i16; 7],
+}
+
+impl PitchState {
+    pub fn new(reverse: bool, master_transpose: i16) -> Self {
+        Self {
+            octave: DEFAULT_OCTAVE,
+            part_octave: 0,
+            reverse,
+            transpose: 0,
+            master_transpose,
+            key_signature: [0; 7],
+        }
+    }
+
+    pub fn apply(&mut self, command: &PartCommand) {
+        match command {
+            PartCommand::Octave(o) => self.octave = o.value as i8 + self.part_octave,
+            PartCommand::PartOctaveChangePositive(o) => {
+                self.part_octave += o.value as i8;
+                self.octave += o.value as i8;
+            }
+            PartCommand::PartOctaveChangeNegative(o) => {
+                self.part_octave -= o.value as i8;
+                self.octave -= o.value as i8;
+            }
+            PartCommand::OctaveUp(_) => self.octave += if self.reverse { -1 } else { 1 },
+            PartCommand::OctaveDown(_) => self.octave += if self.reverse { 1 } else { -1 },
+            PartCommand::OctaveReverse(_) => self.reverse = !self.reverse,
+            PartCommand::AbsoluteTranspose(t) => self.transpose = signed(&t.semitone, t.value),
+            PartCommand::RelativeTranspose(t) => self.transpose += signed(&t.semitone, t.value),
+            PartCommand::MasterTranspose(t) => self.master_transpose = signed(&t.sign, t.value),
+            PartCommand::PartTranspose(t) => self.apply_key_signature(t),
+            _ => {}
+        }
+    }
+
+    fn apply_key_signature(&mut self, key: &PartTranspose) {
+        let accidental = match key.sign {
+            Some(NegativePositiveEqual::Positive) => 1,
+            Some(NegativePositiveEqual::Negative) => -1,
+            Some(NegativePositiveEqual::Equal) | None => 0,
+        };
+
+        if key.notes.is_empty() {
+            self.key_signature = [0; 7];
+            return;
+        }
+
+        for note in &key.notes {
+            if let PartCommand::Note(n) = note.data()
+                && let Ok(command) = NoteCommand::from_str(&n.command)
+            {
+                self.key_signature[command as usize] = accidental;
+            }
+        }
+    }
+
+    pub fn pitch(&self, note: &Note) -> Option<Pitch> {
+        let command = NoteCommand::from_str(&note.command).ok()?;
+        // = ignores the key signature, + - take the place of it
+        let accidental = match (note.natural, &note.semitone) {
+            (true, _) => 0,
+            (false, Some(NegativePositive::Positive)) => 1,
+            (false, Some(NegativePositive::Negative)) => -1,
+            (false, None) => self.key_signature[command.clone() as usize],
+        };
+        let semitone = command.semitone() + accidental + self.transpose + self.master_transpose;
+
+        Some(to_pitch(self.octave, semitone))
+    }
+}
+
+pub struct PitchResolver<'a> {
+    pass2: &'a Pass2Result,
+}
+
+impl<'a> PitchResolver<'a> {
+    pub fn new(pass2: &'a Pass2Result) -> Self {
+        Self { pass2 }
+    }
+
+    pub fn octave_reverse(&self) -> bool {
+        self.pass2
+            .find_macros("Octave")
+            .last()
+            .map(|m| OctaveMacro::from((*m).clone()).value == ReverseNormalOption::Reverse)
+            .unwrap_or(false)
+    }
+
+    pub fn transpose(&self) -> i16 {
+        self.pass2
+            .find_macros("Transpose")
+            .last()
+            .map(|m| TransposeMacro::from((*m).clone()).value as i16)
+            .unwrap_or(0)
+    }
+
+    pub fn resolve(&self, parts: &mut [PartEvents]) -> Vec<Diagnostic> {
+        let reverse = self.octave_reverse();
+        let transpose = self.transpose();
+        let mut states: Vec<(PartSymbol, PitchState)> = vec![];
+        let mut diagnostics = vec![];
+
+        for (part, events) in parts.iter_mut() {
+            // rhythm parts have no pitch
+            if ![Chip::Fm, Chip::Ssg, Chip::Pcm].contains(&part.chip()) {
+                continue;
+            }
+
+            // a part written over several lines continues its state
+            let index = match states.iter().position(|(s, _)| s == part) {
+                Some(index) => index,
+                None => {
+                    states.push((part.clone(), PitchState::new(reverse, transpose)));
+                    states.len() - 1
+                }
+            };
+
+            Self::resolve_events(part, events, &mut states[index].1, &mut diagnostics);
+        }
+
+        diagnostics
+    }
+
+    fn resolve_events(
+        part: &PartSymbol,
+        events: &mut Vec<Event>,
+        state: &mut PitchState,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) {
+        let mut i = 0;
+        while i < events.len() {
+            let code = events[i].code.clone();
+            match &mut events[i].kind {
+                EventKind::Note {
+                    command: PartCommand::Alpeggio(a),
+                    ..
+                } => {
+                    // octave commands inside {{ }} stay in effect
+                    let mut notes = vec![];
+                    for command in &a.notes {
+                        match command.data() {
+                            PartCommand::Note(n) => {
+                                if let Some(pitch) = state.pitch(n) {
+                                    Self::check_octave(part, &code, octave_of(pitch), diagnostics);
+                                    notes.push((command.data().clone(), pitch));
+                                }
+                            }
+                            data => state.apply(data),
+                        }
+                    }
+
+                    let expanded = alpeggio::expand(&events[i], &notes);
+                    let len = expanded.len();
+                    events.splice(i..=i, expanded);
+                    i += len;
+                    continue;
+                }
+                EventKind::Note {
+                    command,
+                    clocks,
+                    pitch,
+                    portamento,
+                    ..
+                } => {
+                    *pitch = match command {
+                        PartCommand::Note(n) => state.pitch(n),
+                        PartCommand::Portamento(p) => {
+                            // octave commands inside { } stay in effect
+                            let mut pitches = vec![];
+                            for command in &p.pitch {
+                                match command {
+                                    PartCommand::Note(n) => pitches.extend(state.pitch(n)),
+                                    _ => state.apply(command),
+                                }
+                            }
+
+                            if let (Some(resolved), [from, .., to]) =
+                                (portamento.as_mut(), &pitches[..])
+                            {
+                                resolved.slide = PortamentoSlide::new(
+                                    &part.chip(),
+                                    *from,
+                                    *to,
+                                    *clocks,
+                                    resolved.delay,
+                                );
+                            }
+                            pitches.first().copied()
+                        }
+                        _ => None,
+                    };
+
+                    if let Some(pitch) = pitch {
+                        Self::check_octave(part, &code, octave_of(*pitch), diagnostics);
+                    }
+                }
+                EventKind::Loop {
+                    body_pre,
+                    body_post,
+                    ..
+                } => {
+                    Self::resolve_events(part, body_pre, state, diagnostics);
+                    Self::resolve_events(part, body_post, state, diagnostics);
+                }
+                EventKind::Command(command) => {
+                    if let PartCommand::Octave(o) = command {
+                        Self::check_octave(part, &code, o.value as i8, diagnostics);
+                    }
+                    state.apply(command);
+                }
+                EventKind::Rest { .. } | EventKind::VolumeShift(_) => {}
+            }
+
+            i += 1;
+        }
+    }
+
+    fn check_octave(part: &PartSymbol, code: &Code, octave: i8, diagnostics: &mut Vec<Diagnostic>) {
+        if !(MIN_OCTAVE..=MAX_OCTAVE).contains(&octave) {
+            diagnostics.push(Diagnostic::error(
+                code,
+                Some(part.clone()),
+                SemanticError::OutOfRange(
+                    "o".to_string(),
+                    octave as i32,
+                    MIN_OCTAVE as i32,
+                    MAX_OCTAVE as i32,
+                ),
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        commands::commands_mml::{OctaveReverse, TemporaryTranspose},
+        events::Clock,
+        length::LengthResolver,
+        options::CompileOptions,
+        part_command::PartCommandStruct,
+    };
+
+    use super::*;
+
+    fn resolve(mml: &str) -> (Vec<PartEvents>, Vec<Diagnostic>) {
+        let pass2 = crate::parse("", mml).unwrap();
+
+        let options = CompileOptions::default();
+        let (mut parts, _) = LengthResolver::new(&pass2, &options).resolve();
+        let diagnostics = PitchResolver::new(&pass2).resolve(&mut parts);
+        (parts, diagnostics)
+    }
+
+    fn pitches(events: &[Event]) -> Vec<Pitch> {
+        events
+            .iter()
+            .filter_map(|e| match &e.kind {
+                EventKind::Note { pitch, .. } => *pitch,
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_pitch() {
+        assert_eq!(60, to_pitch(4, 0));
+        assert_eq!(69, to_pitch(4, 9));
+        assert_eq!(4, octave_of(71));
+        assert_eq!(5, octave_of(72));
+        assert_eq!(0, octave_of(23));
+    }
+
+    #[test]
+    fn test_resolve_octave() {
+        let (parts, diagnostics) = resolve("A\tc d+ e- o5 c > c < < b\nA\tc\n");
+
+        assert!(diagnostics.is_empty());
+        assert_eq!(vec![60, 63, 63, 72, 84, 71], pitches(&parts[0].1));
+        // the part continues on the next line
+        assert_eq!(vec![60], pitches(&parts[1].1));
+    }
+
+    #[test]
+    fn test_resolve_octave_reverse() {
+        let (parts, _) = resolve("#Octave\tReverse\n\nA\tc > c\nG\tc < c\n");
+        assert_eq!(vec![60, 48], pitches(&parts[0].1));
+        assert_eq!(vec![60, 72], pitches(&parts[1].1));
+
+        let mut state = PitchState::new(false, 0);
+        state.apply(
+            &OctaveReverse {
+                command: "X".to_string(),
+            }
+            .to_variant(),
+        );
+        assert!(state.reverse);
+    }
+
+    #[test]
+    fn test_resolve_transpose() {
+        let (parts, _) = resolve("#Transpose\t-12\n\nA\tc\nK\tr\n");
+        assert_eq!(vec![48], pitches(&parts[0].1));
+
+        let mut state = PitchState::new(false, 2);
+        let transpose = TemporaryTranspose {
+            command: "_".to_string(),
+            semitone: Some(NegativePositive::Positive),
+            value: 3,
+        };
+        state.apply(&transpose.clone().to_variant());
+        assert_eq!(3, state.transpose);
+        state.apply(
+            &TemporaryTranspose {
+                command: "__".to_string(),
+                ..transpose
+            }
+            .to_variant(),
+        );
+        assert_eq!(6, state.transpose);
+    }
+
+    #[test]
+    fn test_resolve_octave_range() {
+        let (parts, diagnostics) = resolve("A\to8 b > c\n");
+
+        assert_eq!(vec![119, 120], pitches(&parts[0].1));
+        assert_eq!(1, diagnostics.len());
+        assert_eq!(
+            SemanticError::OutOfRange("o".to_string(), 9, 1, 8),
+            diagnostics[0].error
+        );
+
+        let clocks: Vec<Clock> = parts[0].1.iter().filter_map(|e| e.clocks()).collect();
+        assert_eq!(vec![24, 24], clocks);
+    }
+
+    #[test]
+    fn test_key_signature() {
+        // _{+fc} (D major)
+        let (parts, _) = resolve("A\t_{+fc} d e f g a b > c d\n");
+        assert_eq!(vec![62, 64, 66, 67, 69, 71, 73, 74], pitches(&parts[0].1));
+
+        // _{-bea} (C minor)
+        let (parts, _) = resolve("A\t_{-bea} c d e f g a b\n");
+        assert_eq!(vec![60, 62, 63, 65, 67, 68, 70], pitches(&parts[0].1));
+    }
+
+    #[test]
+    fn test_key_signature_natural() {
+        // f= and c= are played without the key signature, f+ is not doubled
+        let (parts, _) = resolve("A\t_{+fc} f f= c= c f+ f-\n");
+        assert_eq!(vec![66, 65, 60, 61, 66, 64], pitches(&parts[0].1));
+    }
+
+    #[test]
+    fn test_key_signature_reset() {
+        // _{=f} cancels f only, _{=} cancels all of them
+        let (parts, _) = resolve("A\t_{+fcg} f c _{=f} f c _{=} f c\n");
+        assert_eq!(vec![66, 61, 65, 61, 65, 60], pitches(&parts[0].1));
+    }
+
+    #[test]
+    fn test_key_signature_continues() {
+        let (parts, _) = resolve("A\t_{-b} b\nA\tb b=\nB\tb\n");
+        assert_eq!(vec![70], pitches(&parts[0].1));
+        assert_eq!(vec![70, 71], pitches(&parts[1].1));
+        assert_eq!(vec![71], pitches(&parts[2].1));
+    }
+}
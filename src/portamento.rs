@@ -0,0 +1,212 @@
+use crate::{
+    commands::commands_mml::Portamento,
+    errors::SemanticError,
+    events::Clock,
+    length::LengthState,
+    models::{Chip, DivisorClock},
+    pitch::{Pitch, octave_of},
+};
+
+// F-Number of o?c .. o?b, one octave is 0x26a detune steps
+const FM_FNUM: [i32; 12] = [
+    0x026a, 0x028f, 0x02b6, 0x02df, 0x030b, 0x0339, 0x036a, 0x039e, 0x03d5, 0x0410, 0x044e, 0x048f,
+];
+// tone period of o1c .. o1b, halved for each octave
+const SSG_TONE: [i32; 12] = [
+    0x0ee8, 0x0e12, 0x0d48, 0x0c89, 0x0bd5, 0x0b2b, 0x0a8a, 0x09f3, 0x0964, 0x08dd, 0x085e, 0x07e6,
+];
+// ADPCM delta-N of o5c .. o5b, doubled for each octave
+const PCM_DELTA_N: [i32; 12] = [
+    0x3132, 0x3420, 0x373a, 0x3a83, 0x3dfe, 0x41af, 0x4597, 0x49bb, 0x4e1e, 0x52c4, 0x57b1, 0x5ce8,
+];
+const PCM_BASE_OCTAVE: i8 = 5;
+
+// detune slide of { }, the driver adds `per_clock` to the detune every clock
+// after `delay`, plus 1 toward the target while `remainder` lasts
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PortamentoSlide {
+    pub from: Pitch,
+    pub to: Pitch,
+    // 音長2 in clocks
+    pub delay: Clock,
+    // clocks the slide takes
+    pub clocks: Clock,
+    // detune from `from` to `to`
+    pub total: i32,
+    pub per_clock: i32,
+    pub remainder: i32,
+}
+
+impl PortamentoSlide {
+    pub fn new(chip: &Chip, from: Pitch, to: Pitch, clocks: Clock, delay: Clock) -> Option<Self> {
+        let total = detune(chip, to)? - detune(chip, from)?;
+        let slide = clocks.saturating_sub(delay).max(1) as i32;
+
+        Some(Self {
+            from,
+            to,
+            delay,
+            clocks: slide as Clock,
+            total,
+            per_clock: total / slide,
+            remainder: total % slide,
+        })
+    }
+
+    // detune applied at the `clock`th clock of the note
+    pub fn detune_at(&self, clock: Clock) -> i32 {
+        let elapsed = clock.saturating_sub(self.delay).min(self.clocks) as i32;
+        self.per_clock * elapsed + self.remainder.signum() * elapsed.min(self.remainder.abs())
+    }
+}
+
+// { } as resolved by the semantic passes, the node keeps the parsed values
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResolvedPortamento {
+    // 音長2 in clocks, resolved by the length pass
+    pub delay: Clock,
+    // resolved by the pitch pass
+    pub slide: Option<PortamentoSlide>,
+}
+
+// resolves 音長2 into clocks, 音長1 is already resolved into `clocks`
+pub fn resolve(
+    portamento: &Portamento,
+    clocks: Clock,
+    state: &LengthState,
+) -> Result<ResolvedPortamento, SemanticError> {
+    let delay = match portamento.length2 {
+        Some(length) => state.resolve(Some(&DivisorClock::Divisor(length)), 0)?,
+        None => 0,
+    };
+
+    // the slide needs at least one clock
+    if delay >= clocks && clocks > 0 {
+        return Err(SemanticError::OutOfRange(
+            portamento.begin_command.clone(),
+            delay as i32,
+            0,
+            clocks as i32 - 1,
+        ));
+    }
+
+    Ok(ResolvedPortamento { delay, slide: None })
+}
+
+// position of the pitch in detune steps of the chip, higher pitch is larger
+fn detune(chip: &Chip, pitch: Pitch) -> Option<i32> {
+    let octave = octave_of(pitch);
+    let semitone = pitch.rem_euclid(12) as usize;
+
+    match chip {
+        // F-Number beyond the octave carries into the block
+        Chip::Fm => Some(octave as i32 * FM_FNUM[0] + FM_FNUM[semitone]),
+        // SSG detune lowers the tone period
+        Chip::Ssg => {
+            let shift = (octave - 1).clamp(0, 15);
+            Some(-(SSG_TONE[semitone] >> shift))
+        }
+        Chip::Pcm => {
+            let shift = octave - PCM_BASE_OCTAVE;
+            Some(if shift >= 0 {
+                PCM_DELTA_N[semitone] << shift.min(3)
+            } else {
+                PCM_DELTA_N[semitone] >> (-shift).min(15)
+            })
+        }
+        Chip::RhythmSelect | Chip::RhythmDefine => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        events::EventKind, length::LengthResolver, options::CompileOptions,
+        part_command::PartCommand, pitch::PitchResolver,
+    };
+
+    use super::*;
+
+    fn resolve_mml(mml: &str) -> (EventKind, Vec<SemanticError>) {
+        let pass2 = crate::parse("", mml).unwrap();
+
+        let options = CompileOptions::default();
+        let (mut parts, mut diagnostics) = LengthResolver::new(&pass2, &options).resolve();
+        diagnostics.extend(PitchResolver::new(&pass2).resolve(&mut parts));
+
+        (
+            parts[0].1[0].kind.clone(),
+            diagnostics.into_iter().map(|d| d.error).collect(),
+        )
+    }
+
+    #[test]
+    fn test_resolve_portamento() {
+        let (kind, errors) = resolve_mml("A {b>c}4,16\n");
+        assert!(errors.is_empty());
+
+        let EventKind::Note {
+            command: PartCommand::Portamento(_),
+            pitch,
+            portamento: Some(p),
+            ..
+        } = kind
+        else {
+            panic!("unexpected event: {kind:?}");
+        };
+        assert_eq!(Some(71), pitch);
+        assert_eq!(6, p.delay);
+        assert_eq!(PortamentoSlide::new(&Chip::Fm, 71, 72, 24, 6), p.slide);
+
+        // followed by other commands
+        let (kind, _) = resolve_mml("A {ce}8. d\n");
+        let EventKind::Note {
+            command: PartCommand::Portamento(_),
+            portamento: Some(p),
+            ..
+        } = kind
+        else {
+            panic!("unexpected event: {kind:?}");
+        };
+        assert_eq!(PortamentoSlide::new(&Chip::Fm, 60, 64, 18, 0), p.slide);
+
+        let (_, errors) = resolve_mml("G {b>c}4,4\n");
+        assert_eq!(
+            vec![SemanticError::OutOfRange("{".to_string(), 24, 0, 23)],
+            errors
+        );
+    }
+
+    #[test]
+    fn test_slide_fm() {
+        // o4c -> o4d over a quarter note
+        let slide = PortamentoSlide::new(&Chip::Fm, 60, 62, 24, 0).unwrap();
+        assert_eq!(0x2b6 - 0x26a, slide.total);
+        assert_eq!(3, slide.per_clock);
+        assert_eq!(4, slide.remainder);
+        assert_eq!(slide.total, slide.detune_at(24));
+
+        // across the octave, with delay
+        let slide = PortamentoSlide::new(&Chip::Fm, 71, 72, 24, 8).unwrap();
+        assert_eq!(0x26a * 5 + 0x26a - (0x26a * 4 + 0x48f), slide.total);
+        assert_eq!(16, slide.clocks);
+        assert_eq!(0, slide.detune_at(8));
+        assert_eq!(slide.total, slide.detune_at(30));
+    }
+
+    #[test]
+    fn test_slide_ssg_pcm() {
+        let slide = PortamentoSlide::new(&Chip::Ssg, 62, 60, 12, 0).unwrap();
+        assert_eq!(-((0x0ee8 >> 3) - (0x0d48 >> 3)), slide.total);
+        assert!(slide.per_clock < 0 && slide.remainder <= 0);
+        assert_eq!(slide.total, slide.detune_at(12));
+
+        let slide = PortamentoSlide::new(&Chip::Pcm, 72, 84, 48, 0).unwrap();
+        assert_eq!(0x3132, slide.total);
+
+        assert_eq!(
+            None,
+            PortamentoSlide::new(&Chip::RhythmSelect, 60, 62, 24, 0)
+        );
+    }
+}
@@ -0,0 +1,358 @@
+use std::fmt;
+
+use crate::{
+    diagnostics::Diagnostic,
+    events::{Clock, Event, EventKind},
+    length::{LengthResolver, MAX_STEPS},
+    meta_models::Pass2Result,
+    models::{Chip, DivisorClock, ExtendPartSymbol, Fm3ExtendMacro, PartSymbol, PpzExtendMacro},
+    options::CompileOptions,
+    part_command::PartCommand,
+    timeline::{PartTimeline, Seconds, Timeline},
+};
+
+// 0x80 at the end of each part
+const PART_END_BYTES: usize = 1;
+
+// lengths of a part, as MC /V prints after compiling
+#[derive(Debug, Clone, PartialEq)]
+pub struct PartReport {
+    pub part: Option<PartSymbol>,
+    // declared by #FM3Extend or #PPZExtend
+    pub extend_part: Option<ExtendPartSymbol>,
+    pub clocks: Clock,
+    // clock of L, None when the part stops at its end
+    pub loop_start: Option<Clock>,
+    pub loop_clocks: Option<Clock>,
+    pub seconds: Seconds,
+    pub loop_seconds: Option<Seconds>,
+    // rough estimate of the size of the part in the .M file, counted from the
+    // usual encoding of each command rather than by compiling it
+    pub bytes: usize,
+}
+
+impl PartReport {
+    fn new(timeline: &Timeline, part: &PartTimeline, bytes: usize) -> Self {
+        let loop_start = part.loop_point().map(|e| e.clock);
+        let seconds = timeline.time_at(part.clocks);
+
+        Self {
+            part: Some(part.part.clone()),
+            extend_part: None,
+            clocks: part.clocks,
+            loop_start,
+            loop_clocks: part.loop_clocks(),
+            seconds,
+            loop_seconds: loop_start.map(|start| seconds - timeline.time_at(start)),
+            bytes,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Report {
+    pub parts: Vec<PartReport>,
+}
+
+impl Report {
+    pub fn new(pass2: &Pass2Result, options: &CompileOptions) -> (Self, Vec<Diagnostic>) {
+        let (timeline, diagnostics) = Timeline::new(pass2, options);
+        (Self::from_timeline(pass2, options, &timeline), diagnostics)
+    }
+
+    // the sizes are counted again from the commands, as the timeline has the
+    // loops unrolled and the song cut by #Jump
+    pub fn from_timeline(
+        pass2: &Pass2Result,
+        options: &CompileOptions,
+        timeline: &Timeline,
+    ) -> Self {
+        let mut parts = timeline
+            .parts
+            .iter()
+            .map(|p| PartReport::new(timeline, p, parts_bytes(pass2, options, &p.part)))
+            .collect::<Vec<_>>();
+        parts.extend(Self::extend_parts(pass2, options, timeline));
+
+        Self { parts }
+    }
+
+    pub fn total_bytes(&self) -> usize {
+        self.parts.iter().map(|p| p.bytes).sum()
+    }
+
+    // the timeline only has A to R, an extended part is timed alone as a part
+    // of its chip, on the tempo of the song
+    fn extend_parts(
+        pass2: &Pass2Result,
+        options: &CompileOptions,
+        timeline: &Timeline,
+    ) -> Vec<PartReport> {
+        let fm3_extend = pass2
+            .find_macros("FM3Extend")
+            .last()
+            .map(|m| Fm3ExtendMacro::from((*m).clone()));
+        let ppz_extend = pass2
+            .find_macros("PPZExtend")
+            .last()
+            .map(|m| PpzExtendMacro::from((*m).clone()));
+
+        let mut symbols: Vec<&ExtendPartSymbol> = vec![];
+        for (part, _) in &pass2.extend_parts {
+            if !symbols.contains(&part) {
+                symbols.push(part);
+            }
+        }
+
+        symbols
+            .into_iter()
+            .filter_map(|symbol| {
+                let host = match symbol.chip(fm3_extend.as_ref(), ppz_extend.as_ref()) {
+                    Some(Chip::Pcm) => PartSymbol::J,
+                    _ => PartSymbol::C,
+                };
+                let extend = Pass2Result {
+                    parts: pass2
+                        .extend_parts
+                        .iter()
+                        .filter(|(p, _)| p == symbol)
+                        .map(|(_, commands)| (host.clone(), commands.clone()))
+                        .collect(),
+                    extend_parts: vec![],
+                    ..pass2.clone()
+                };
+
+                let (extend_timeline, _) = Timeline::new(&extend, options);
+                let part = extend_timeline.parts.first()?;
+                let bytes = parts_bytes(&extend, options, &host);
+
+                Some(PartReport {
+                    part: None,
+                    extend_part: Some(symbol.clone()),
+                    ..PartReport::new(timeline, part, bytes)
+                })
+            })
+            .collect()
+    }
+}
+
+fn parts_bytes(pass2: &Pass2Result, options: &CompileOptions, part: &PartSymbol) -> usize {
+    let (events, _) = LengthResolver::new(pass2, options).resolve();
+    events
+        .iter()
+        .filter(|(p, _)| p == part)
+        .map(|(_, events)| events_bytes(events))
+        .sum::<usize>()
+        + PART_END_BYTES
+}
+
+fn events_bytes(events: &[Event]) -> usize {
+    events.iter().map(event_bytes).sum()
+}
+
+// bytes MC writes for an event, a rough estimate from the .M format of PMD
+fn event_bytes(event: &Event) -> usize {
+    match &event.kind {
+        EventKind::Note {
+            command: PartCommand::Alpeggio(a),
+            clocks,
+            ..
+        } => {
+            // expanded into notes of 音長2, see alpeggio::expand
+            let step = match a.length2 {
+                Some(DivisorClock::Clock(step)) => step.max(1) as Clock,
+                _ => 1,
+            };
+            let rest = (a.value2.unwrap_or(0) as Clock).min(*clocks);
+            let notes = (clocks - rest).div_ceil(step) as usize;
+            let ties = if a.value1 { notes.saturating_sub(1) } else { 0 };
+
+            notes * 2 + ties + if rest > 0 { 2 } else { 0 }
+        }
+        EventKind::Note {
+            command: PartCommand::Portamento(_),
+            portamento,
+            ..
+        } => {
+            // 音長2 is a note tied to the slide
+            4 + if portamento.as_ref().is_some_and(|p| p.delay > 0) {
+                3
+            } else {
+                0
+            }
+        }
+        EventKind::Note { clocks, .. } => {
+            // a note longer than 255 steps is written as c&c
+            let pieces = clocks.div_ceil(MAX_STEPS).max(1) as usize;
+            pieces * 2 + (pieces - 1)
+        }
+        EventKind::Rest { clocks, .. } => clocks.div_ceil(MAX_STEPS).max(1) as usize * 2,
+        EventKind::Loop {
+            body_pre,
+            body_post,
+            ..
+        } => {
+            // [ and ] with the loop count, : jumps out of the last pass
+            let separator = if body_post.is_empty() { 0 } else { 3 };
+            3 + events_bytes(body_pre) + separator + events_bytes(body_post) + 4
+        }
+        EventKind::Command(command) => command_bytes(command),
+        EventKind::VolumeShift(_) => 2,
+    }
+}
+
+fn command_bytes(command: &PartCommand) -> usize {
+    match command {
+        // resolved by MC, nothing is written
+        PartCommand::Nop
+        | PartCommand::Octave(_)
+        | PartCommand::OctaveUp(_)
+        | PartCommand::OctaveDown(_)
+        | PartCommand::OctaveReverse(_)
+        | PartCommand::PartOctaveChangePositive(_)
+        | PartCommand::PartOctaveChangeNegative(_)
+        | PartCommand::DefaultLength(_)
+        | PartCommand::WholeLength(_)
+        | PartCommand::ProcessLastLengthUpdate(_)
+        | PartCommand::ProcessLastLengthAdd(_)
+        | PartCommand::ProcessLastLengthSubtract(_)
+        | PartCommand::ProcessLastLengthMultiply(_)
+        | PartCommand::AbsoluteTranspose(_)
+        | PartCommand::RelativeTranspose(_)
+        | PartCommand::PartTranspose(_)
+        | PartCommand::MasterTranspose(_)
+        | PartCommand::BarLine(_) => 0,
+        // L is kept as an address in the header
+        PartCommand::SongLoop(_) => 0,
+        PartCommand::Tie(_) | PartCommand::Slur(_) => 1,
+        PartCommand::RelativeVolumeUp(v) | PartCommand::RelativeVolumeDown(v) => {
+            1 + v.value.map_or(0, |_| 1)
+        }
+        PartCommand::Quantize2(q) => 2 + q.value2.map_or(0, |_| 2) + q.value3.map_or(0, |_| 2),
+        PartCommand::Tempo1(_) | PartCommand::PanEx(_) => 3,
+        PartCommand::SoftwareLfoA(_) | PartCommand::SoftwareLfoB(_) => 5,
+        PartCommand::SsgPcmSoftwareEnvelope(e) => match e.value5 {
+            None => 5,
+            Some(_) => 7,
+        },
+        // the command and one value
+        _ => 2,
+    }
+}
+
+fn format_time(seconds: Seconds) -> String {
+    let minutes = (seconds / 60.0).floor();
+    format!("{}:{:05.2}", minutes as u32, seconds - minutes * 60.0)
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // the size is not compiled, only estimated
+        writeln!(
+            f,
+            "Part {:>8} {:>8} {:>8} {:>9} {:>9} {:>8}",
+            "Length", "Loop at", "Loop", "Time", "Loop time", "~Bytes"
+        )?;
+
+        let or_dash = |value: Option<String>| value.unwrap_or_else(|| "-".to_string());
+        for p in &self.parts {
+            let part = match (&p.part, &p.extend_part) {
+                (_, Some(extend_part)) => format!("{extend_part:?}"),
+                (Some(part), None) => format!("{part:?}"),
+                (None, None) => "-".to_string(),
+            };
+            writeln!(
+                f,
+                "{:<4} {:>8} {:>8} {:>8} {:>9} {:>9} {:>8}",
+                part,
+                p.clocks,
+                or_dash(p.loop_start.map(|c| c.to_string())),
+                or_dash(p.loop_clocks.map(|c| c.to_string())),
+                format_time(p.seconds),
+                or_dash(p.loop_seconds.map(format_time)),
+                p.bytes,
+            )?;
+        }
+        writeln!(f, "{:<5} {:>54}", "Total", self.total_bytes())?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::timeline::clock_seconds;
+
+    use super::*;
+
+    fn report(mml: &str) -> Report {
+        let pass2 = crate::parse("", mml).unwrap();
+
+        Report::new(&pass2, &CompileOptions::default()).0
+    }
+
+    #[test]
+    fn test_report() {
+        let report = report("#Timer 220\nA c4 L [d8]2 e2\nB c1 L c1\nC r4");
+
+        let a = &report.parts[0];
+        assert_eq!(Some(PartSymbol::A), a.part);
+        assert_eq!(96, a.clocks);
+        assert_eq!(Some(24), a.loop_start);
+        assert_eq!(Some(72), a.loop_clocks);
+        assert!((a.seconds - 96.0 * clock_seconds(220)).abs() < 1e-9);
+        assert!((a.loop_seconds.unwrap() - 72.0 * clock_seconds(220)).abs() < 1e-9);
+
+        assert_eq!(Some(96), report.parts[1].loop_clocks);
+        assert_eq!(None, report.parts[2].loop_start);
+
+        // [ ] is written once
+        assert_eq!(14, a.bytes);
+        assert_eq!(5, report.parts[1].bytes);
+        assert_eq!(3, report.parts[2].bytes);
+    }
+
+    #[test]
+    fn test_report_bytes() {
+        // c1^1^1 as c&c, {ce}4,8 after a note of 8, {{ }} as c&e
+        let bytes = |mml: &str| report(mml).parts[0].bytes;
+        assert_eq!(
+            5 + 7 + 3 + 2 + 14 + 5 + 5 + 1,
+            bytes("A c1^1^1 {ce}4,8 t120 Q8 [c:d]3 {{ce}}4,8 M0,1,2,3\n")
+        );
+        assert_eq!(4 + 1, bytes("A {ce}4\n"));
+    }
+
+    #[test]
+    fn test_report_display() {
+        let report = report("#Timer 220\nA L c1");
+        assert_eq!(
+            "Part   Length  Loop at     Loop      Time Loop time   ~Bytes\n\
+             A          96        0       96   0:01.00   0:01.00        3\n\
+             Total                                                      3\n",
+            report.to_string()
+        );
+    }
+
+    #[test]
+    fn test_report_extend_parts() {
+        let report = report("#Timer 220\n#FM3Extend XY\nA c1\nX c2 L c2\nY c4\nX c1");
+
+        assert_eq!(3, report.parts.len());
+        let x = &report.parts[1];
+        assert_eq!(None, x.part);
+        assert_eq!(Some(ExtendPartSymbol::X), x.extend_part);
+        assert_eq!(192, x.clocks);
+        assert_eq!(Some(48), x.loop_start);
+        assert!((x.seconds - 192.0 * clock_seconds(220)).abs() < 1e-9);
+        assert_eq!(Some(ExtendPartSymbol::Y), report.parts[2].extend_part);
+        assert_eq!(24, report.parts[2].clocks);
+
+        assert_eq!(3 + 7 + 3, report.total_bytes());
+        assert!(
+            report
+                .to_string()
+                .contains("\nX         192       48      144")
+        );
+    }
+}
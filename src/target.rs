@@ -0,0 +1,177 @@
+use crate::volume::VolumeDown;
+
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, strum::EnumString, strum::Display, strum::EnumIter,
+)]
+#[strum(ascii_case_insensitive)]
+pub enum TargetDriver {
+    #[strum(serialize = "PMD")]
+    Pmd, // PMD.COM (OPN)
+    #[default]
+    #[strum(serialize = "PMDB2")]
+    PmdB2, // OPNA + ADPCM
+    #[strum(serialize = "PMDVA")]
+    PmdVa, // PC-88VA ADPCM
+    #[strum(serialize = "PMD86")]
+    Pmd86, // 86PCM
+    #[strum(serialize = "PMDPPZ")]
+    PmdPpz, // PMD86, J part by PPZ8
+    #[strum(serialize = "PMDPPZE")]
+    PmdPpzE, // PMDB2 with PPZ8 extended parts
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PcmKind {
+    Adpcm,
+    Pcm86,
+    Ppz,
+}
+
+impl TargetDriver {
+    // rhythm sound source of the OPNA, used by the K part
+    pub fn has_rhythm(&self) -> bool {
+        !matches!(self, TargetDriver::Pmd)
+    }
+
+    pub fn pcm_kind(&self) -> Option<PcmKind> {
+        match self {
+            TargetDriver::Pmd => None,
+            TargetDriver::PmdB2 | TargetDriver::PmdVa | TargetDriver::PmdPpzE => {
+                Some(PcmKind::Adpcm)
+            }
+            TargetDriver::Pmd86 => Some(PcmKind::Pcm86),
+            TargetDriver::PmdPpz => Some(PcmKind::Ppz),
+        }
+    }
+
+    pub fn has_pcm(&self) -> bool {
+        self.pcm_kind().is_some()
+    }
+
+    pub fn supports_pan_ex(&self) -> bool {
+        matches!(
+            self,
+            TargetDriver::Pmd86 | TargetDriver::PmdPpz | TargetDriver::PmdPpzE
+        )
+    }
+
+    // 数値3 リリース開始位置 ※PMDPPZ,PMDPPZEでは無効
+    pub fn supports_pcm_release(&self) -> bool {
+        !matches!(self, TargetDriver::PmdPpz | TargetDriver::PmdPpzE)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TargetProfile {
+    pub driver: TargetDriver,
+    // PDR/PPSDRV is resident, SSG rhythm plays SSGPCM
+    pub ppsdrv: bool,
+    // PMD86 /S option, PCM behaves like ADPCM
+    pub adpcm_compatible: bool,
+    // /DF /DS /DP /DR options of the driver, None for its defaults
+    pub volume_down: Option<VolumeDown>,
+}
+
+impl TargetProfile {
+    pub fn new(driver: TargetDriver) -> Self {
+        Self {
+            driver,
+            ..Default::default()
+        }
+    }
+
+    // unit of the PCM repeat addresses of @ in bytes
+    pub fn pcm_repeat_address_unit(&self) -> Option<i32> {
+        match self.driver.pcm_kind()? {
+            PcmKind::Adpcm => Some(16),
+            PcmKind::Pcm86 if self.adpcm_compatible => Some(32),
+            PcmKind::Pcm86 | PcmKind::Ppz => Some(1),
+        }
+    }
+
+    pub fn pcm_repeat_address_range(&self) -> (i32, i32) {
+        if self.driver == TargetDriver::Pmd86 && self.adpcm_compatible {
+            // multiplied by 32 in the driver
+            (-1024, 1023)
+        } else {
+            (-32768, 32767)
+        }
+    }
+
+    pub fn pcm_repeat_address_bytes(&self, value: i32) -> Option<i32> {
+        self.pcm_repeat_address_unit().map(|unit| value * unit)
+    }
+
+    pub fn max_ssg_rhythm_tone(&self) -> u16 {
+        if self.ppsdrv { 16383 } else { 2047 }
+    }
+
+    // the base of the relative values of #VolumeDown
+    pub fn driver_volume_down(&self) -> VolumeDown {
+        self.volume_down.unwrap_or(VolumeDown {
+            // the FM of OPNA is lowered to balance with the SSG
+            fm: if self.driver == TargetDriver::Pmd {
+                0
+            } else {
+                16
+            },
+            ..Default::default()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    #[test]
+    fn test_driver_from_str() {
+        assert_eq!(TargetDriver::Pmd, TargetDriver::from_str("PMD").unwrap());
+        assert_eq!(
+            TargetDriver::PmdB2,
+            TargetDriver::from_str("pmdb2").unwrap()
+        );
+        assert_eq!(
+            TargetDriver::PmdPpzE,
+            TargetDriver::from_str("PMDPPZE").unwrap()
+        );
+        assert!(TargetDriver::from_str("PMD98").is_err());
+        assert_eq!("PMD86", TargetDriver::Pmd86.to_string());
+    }
+
+    #[test]
+    fn test_pcm_repeat_address_unit() {
+        assert_eq!(
+            None,
+            TargetProfile::new(TargetDriver::Pmd).pcm_repeat_address_unit()
+        );
+        assert_eq!(
+            Some(16),
+            TargetProfile::new(TargetDriver::PmdB2).pcm_repeat_address_unit()
+        );
+        assert_eq!(
+            Some(16),
+            TargetProfile::new(TargetDriver::PmdVa).pcm_repeat_address_unit()
+        );
+        assert_eq!(
+            Some(1),
+            TargetProfile::new(TargetDriver::Pmd86).pcm_repeat_address_unit()
+        );
+        assert_eq!(
+            Some(1),
+            TargetProfile::new(TargetDriver::PmdPpz).pcm_repeat_address_unit()
+        );
+
+        let profile = TargetProfile {
+            driver: TargetDriver::Pmd86,
+            ppsdrv: false,
+            adpcm_compatible: true,
+            volume_down: None,
+        };
+        assert_eq!(Some(32), profile.pcm_repeat_address_unit());
+        assert_eq!(Some(-3200), profile.pcm_repeat_address_bytes(-100));
+        assert_eq!((-1024, 1023), profile.pcm_repeat_address_range());
+    }
+}
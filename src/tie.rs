@@ -0,0 +1,303 @@
+use crate::{
+    diagnostics::Diagnostic,
+    errors::SemanticError,
+    events::{Event, EventKind, PartEvents},
+    meta_models::Code,
+    models::PartSymbol,
+    part_command::PartCommand,
+};
+
+// & and && waiting for the next note
+#[derive(Debug, Clone)]
+struct PendingTie {
+    code: Code,
+    slur: bool,
+    // & generated by {{ }}
+    split: bool,
+    // events pushed after the note the tie belongs to
+    merged_len: usize,
+}
+
+// merges c&c into one sustained note, marks c&&d as legato
+#[derive(Debug, Default)]
+pub struct TieMerger;
+
+impl TieMerger {
+    pub fn new() -> Self {
+        Self
+    }
+
+    // run after the pitch and gate passes
+    pub fn resolve(&self, parts: &mut [PartEvents]) -> Vec<Diagnostic> {
+        let mut diagnostics = vec![];
+
+        for (part, events) in parts.iter_mut() {
+            *events = Self::merge_events(part, std::mem::take(events), &mut diagnostics);
+        }
+
+        diagnostics
+    }
+
+    fn merge_events(
+        part: &PartSymbol,
+        events: Vec<Event>,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) -> Vec<Event> {
+        let mut merged: Vec<Event> = vec![];
+        let mut last_note: Option<usize> = None;
+        let mut pending: Option<PendingTie> = None;
+
+        for mut event in events {
+            match &mut event.kind {
+                EventKind::Command(PartCommand::Tie(_) | PartCommand::Slur(_)) => {
+                    if last_note.is_none() {
+                        diagnostics.push(Diagnostic::error(
+                            &event.code,
+                            Some(part.clone()),
+                            SemanticError::NothingToModify(Self::command_name(&event)),
+                        ));
+                        continue;
+                    }
+
+                    pending = Some(PendingTie {
+                        code: event.code.clone(),
+                        slur: matches!(event.kind, EventKind::Command(PartCommand::Slur(_))),
+                        split: event.split,
+                        merged_len: merged.len(),
+                    });
+                    continue;
+                }
+                EventKind::Note { .. } => {
+                    if let (Some(tie), Some(index)) = (pending.take(), last_note) {
+                        if Self::can_merge(&tie, &merged, index, &event) {
+                            Self::extend(&mut merged[index], &event);
+                            continue;
+                        }
+
+                        Self::connect(part, &tie, &mut merged[index], &event, diagnostics);
+                    }
+
+                    last_note = Some(merged.len());
+                }
+                EventKind::Loop {
+                    body_pre,
+                    body_post,
+                    ..
+                } => {
+                    // a tie does not reach into the loop
+                    Self::flush(&mut pending, last_note, &mut merged);
+                    *body_pre = Self::merge_events(part, std::mem::take(body_pre), diagnostics);
+                    *body_post = Self::merge_events(part, std::mem::take(body_post), diagnostics);
+                    last_note = None;
+                }
+                EventKind::Rest { .. } => {
+                    Self::flush(&mut pending, last_note, &mut merged);
+                    last_note = None;
+                }
+                EventKind::Command(_) | EventKind::VolumeShift(_) => {}
+            }
+
+            merged.push(event);
+        }
+
+        Self::flush(&mut pending, last_note, &mut merged);
+        merged
+    }
+
+    // c&c with nothing in between sounds as one note
+    fn can_merge(tie: &PendingTie, merged: &[Event], index: usize, next: &Event) -> bool {
+        let previous = &merged[index];
+        if tie.slur || tie.merged_len != merged.len() || previous.split || next.split {
+            return false;
+        }
+
+        match (&previous.kind, &next.kind) {
+            (
+                EventKind::Note {
+                    command: PartCommand::Note(_),
+                    pitch: p1,
+                    ..
+                },
+                EventKind::Note {
+                    command: PartCommand::Note(_),
+                    pitch: p2,
+                    ..
+                },
+            ) => p1 == p2,
+            _ => false,
+        }
+    }
+
+    fn extend(previous: &mut Event, next: &Event) {
+        let EventKind::Note {
+            clocks: next_clocks,
+            gate: next_gate,
+            legato: next_legato,
+            ..
+        } = &next.kind
+        else {
+            return;
+        };
+
+        if let EventKind::Note {
+            clocks,
+            gate,
+            legato,
+            ..
+        } = &mut previous.kind
+        {
+            *gate = next_gate.map(|g| *clocks + g);
+            *clocks += next_clocks;
+            *legato = *next_legato;
+        }
+    }
+
+    fn connect(
+        part: &PartSymbol,
+        tie: &PendingTie,
+        previous: &mut Event,
+        next: &Event,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) {
+        let (
+            EventKind::Note {
+                pitch: p1, legato, ..
+            },
+            EventKind::Note { pitch: p2, .. },
+        ) = (&mut previous.kind, &next.kind)
+        else {
+            return;
+        };
+
+        *legato = true;
+
+        // MC compiles & between different pitches as &&
+        if !tie.slur && !tie.split && p1.is_some() && p2.is_some() && p1 != p2 {
+            diagnostics.push(Diagnostic::warning(
+                &tie.code,
+                Some(part.clone()),
+                SemanticError::TieDifferentPitch,
+            ));
+        }
+    }
+
+    // a tie without the following note keeps the key on
+    fn flush(pending: &mut Option<PendingTie>, last_note: Option<usize>, merged: &mut [Event]) {
+        if let (Some(_), Some(index)) = (pending.take(), last_note)
+            && let EventKind::Note { legato, .. } = &mut merged[index].kind
+        {
+            *legato = true;
+        }
+    }
+
+    fn command_name(event: &Event) -> String {
+        match &event.kind {
+            EventKind::Command(command) => command.command_name(),
+            _ => String::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        events::Clock,
+        gate::GateResolver,
+        length::LengthResolver,
+        options::CompileOptions,
+        pitch::{Pitch, PitchResolver},
+    };
+
+    use super::*;
+
+    fn merge(mml: &str) -> (Vec<Event>, Vec<Diagnostic>) {
+        let pass2 = crate::parse("", mml).unwrap();
+
+        let options = CompileOptions::default();
+        let (mut parts, mut diagnostics) = LengthResolver::new(&pass2, &options).resolve();
+        diagnostics.extend(PitchResolver::new(&pass2).resolve(&mut parts));
+        diagnostics.extend(GateResolver::new(&pass2, &options).resolve(&mut parts));
+        diagnostics.extend(TieMerger::new().resolve(&mut parts));
+
+        (parts.remove(0).1, diagnostics)
+    }
+
+    fn notes(events: &[Event]) -> Vec<(Option<Pitch>, Clock, Option<Clock>, bool)> {
+        events
+            .iter()
+            .filter_map(|e| match &e.kind {
+                EventKind::Note {
+                    pitch,
+                    clocks,
+                    gate,
+                    legato,
+                    ..
+                } => Some((*pitch, *clocks, *gate, *legato)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_merge_tie() {
+        let (events, diagnostics) = merge("A c4&c4&8 d4\n");
+
+        assert!(diagnostics.is_empty());
+        assert_eq!(2, events.len());
+        assert_eq!(
+            vec![
+                (Some(60), 60, Some(60), false),
+                (Some(62), 24, Some(24), false)
+            ],
+            notes(&events)
+        );
+
+        // c4&8. is c4&c8., d4&&8 is d4&&d8
+        let (events, _) = merge("A c4&8. d4&&8\n");
+        assert_eq!(
+            vec![
+                (Some(60), 42, Some(42), false),
+                (Some(62), 24, Some(24), true),
+                (Some(62), 12, Some(12), false)
+            ],
+            notes(&events)
+        );
+    }
+
+    #[test]
+    fn test_merge_slur() {
+        let (events, diagnostics) = merge("A c4&&d4&&d4&&\n");
+
+        assert!(diagnostics.is_empty());
+        assert_eq!(
+            vec![
+                (Some(60), 24, Some(24), true),
+                (Some(62), 24, Some(24), true),
+                (Some(62), 24, Some(24), true),
+            ],
+            notes(&events)
+        );
+    }
+
+    #[test]
+    fn test_tie_different_pitch() {
+        let (events, diagnostics) = merge("A c4&c+4\n");
+
+        assert_eq!(
+            vec![
+                (Some(60), 24, Some(24), true),
+                (Some(61), 24, Some(24), false)
+            ],
+            notes(&events)
+        );
+        assert_eq!(1, diagnostics.len());
+        assert!(!diagnostics[0].is_error());
+        assert_eq!(SemanticError::TieDifferentPitch, diagnostics[0].error);
+
+        let (_, diagnostics) = merge("A &c4\n");
+        assert_eq!(
+            SemanticError::NothingToModify("&".to_string()),
+            diagnostics[0].error
+        );
+    }
+}
@@ -0,0 +1,463 @@
+use crate::{
+    diagnostics::Diagnostic,
+    errors::SemanticError,
+    events::{Clock, Event, EventKind, PartEvents},
+    gate::GateResolver,
+    jump::{jump_measure, skip_to_measure},
+    length::LengthResolver,
+    measure::{MeasureCounter, Position},
+    meta_models::{Code, Pass2Result},
+    models::{PartSymbol, TempoMacro},
+    options::CompileOptions,
+    part_command::PartCommand,
+    pitch::PitchResolver,
+    tie::TieMerger,
+    unroll::LoopUnroller,
+    volume::VolumeResolver,
+};
+
+pub type Seconds = f64;
+
+// OPNA at 7.9872MHz counts Timer-B up every 2304 master clocks,
+// one internal clock of PMD is one overflow of Timer-B
+pub const TIMER_B_STEP: Seconds = 2304.0 / 7_987_200.0;
+// Timer-B when neither #Tempo, #Timer, t nor T is given
+pub const DEFAULT_TIMER_B: u8 = 200;
+pub const MIN_TEMPO: u8 = 18;
+pub const MAX_TIMER_B: u8 = 250;
+
+// t: clock 48 is played `tempo` times a minute, rounded to a Timer-B value
+pub fn tempo_to_timer_b(tempo: u8) -> u8 {
+    let steps = (60.0 / (48.0 * tempo.max(1) as Seconds) / TIMER_B_STEP).round();
+    (256.0 - steps).clamp(0.0, MAX_TIMER_B as Seconds) as u8
+}
+
+pub fn clock_seconds(timer_b: u8) -> Seconds {
+    (256 - timer_b as u32) as Seconds * TIMER_B_STEP
+}
+
+// t and T change the tempo of the whole song, whichever part they are in
+#[derive(Debug, Clone, PartialEq)]
+pub struct TempoChange {
+    pub clock: Clock,
+    pub timer_b: u8,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimedEvent {
+    pub clock: Clock,
+    pub position: Position,
+    pub time: Seconds,
+    pub event: Event,
+}
+
+impl TimedEvent {
+    pub fn end_clock(&self) -> Clock {
+        self.clock + self.event.clocks().unwrap_or(0)
+    }
+}
+
+// the lines of a part joined and the loops unrolled
+#[derive(Debug, Clone, PartialEq)]
+pub struct PartTimeline {
+    pub part: PartSymbol,
+    pub events: Vec<TimedEvent>,
+    pub clocks: Clock,
+}
+
+impl PartTimeline {
+    // L, where the part comes back after its end
+    pub fn loop_point(&self) -> Option<&TimedEvent> {
+        self.events
+            .iter()
+            .find(|e| matches!(e.event.kind, EventKind::Command(PartCommand::SongLoop(_))))
+    }
+
+    pub fn loop_clocks(&self) -> Option<Clock> {
+        self.loop_point().map(|e| self.clocks - e.clock)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Timeline {
+    pub parts: Vec<PartTimeline>,
+    pub tempo_changes: Vec<TempoChange>,
+}
+
+impl Timeline {
+    pub fn new(pass2: &Pass2Result, options: &CompileOptions) -> (Self, Vec<Diagnostic>) {
+        let (mut parts, mut diagnostics) = LengthResolver::new(pass2, options).resolve();
+        diagnostics.extend(PitchResolver::new(pass2).resolve(&mut parts));
+        diagnostics.extend(GateResolver::new(pass2, options).resolve(&mut parts));
+        diagnostics.extend(TieMerger::new().resolve(&mut parts));
+
+        let (mut parts, unroll_diagnostics) = LoopUnroller::new().unroll(&parts);
+        diagnostics.extend(unroll_diagnostics);
+        // ( ) inside loops count on every pass
+        diagnostics.extend(VolumeResolver::new(pass2, options).resolve(&mut parts));
+
+        let zenlen = LengthResolver::new(pass2, options).zenlen();
+        let jump = jump_measure(pass2, options);
+        let parts = match jump {
+            Some(measure) => skip_to_measure(&parts, zenlen, measure),
+            None => parts,
+        };
+
+        let mut timeline = Self::from_events(
+            Self::initial_timer_b(pass2),
+            zenlen,
+            &parts,
+            &mut diagnostics,
+        );
+        // measures are counted from the head of the song
+        if let Some(measure) = jump {
+            for event in timeline.parts.iter_mut().flat_map(|p| p.events.iter_mut()) {
+                event.position.measure = event.position.measure.saturating_add(measure - 1);
+            }
+        }
+        timeline.locate(&mut diagnostics);
+        (timeline, diagnostics)
+    }
+
+    // #Tempo and #Timer put t or T at the head of the song, the last one wins
+    pub fn initial_timer_b(pass2: &Pass2Result) -> u8 {
+        pass2
+            .macros
+            .iter()
+            .rev()
+            .find_map(|m| {
+                if m.key.eq_ignore_ascii_case("Tempo") {
+                    Some(tempo_to_timer_b(TempoMacro::from(m.clone()).value))
+                } else if m.key.eq_ignore_ascii_case("Timer") {
+                    Some(TempoMacro::from(m.clone()).value.min(MAX_TIMER_B))
+                } else {
+                    None
+                }
+            })
+            .unwrap_or(DEFAULT_TIMER_B)
+    }
+
+    // `parts` are flat, see LoopUnroller
+    pub fn from_events(
+        timer_b: u8,
+        zenlen: u8,
+        parts: &[PartEvents],
+        diagnostics: &mut Vec<Diagnostic>,
+    ) -> Self {
+        let mut timelines: Vec<PartTimeline> = vec![];
+        let mut counters: Vec<MeasureCounter> = vec![];
+        let mut tempo_changes = vec![TempoChange { clock: 0, timer_b }];
+
+        for (part, events) in parts {
+            // a part written over several lines continues its clock
+            let index = match timelines.iter().position(|t| &t.part == part) {
+                Some(index) => index,
+                None => {
+                    timelines.push(PartTimeline {
+                        part: part.clone(),
+                        events: vec![],
+                        clocks: 0,
+                    });
+                    counters.push(MeasureCounter::new(zenlen));
+                    timelines.len() - 1
+                }
+            };
+            let timeline = &mut timelines[index];
+            let counter = &mut counters[index];
+
+            for event in events {
+                match &event.kind {
+                    EventKind::Command(PartCommand::WholeLength(c)) => counter.set_zenlen(c.value),
+                    EventKind::Command(PartCommand::BarLine(c))
+                        if c.parts.is_none() && counter.position().clock > 0 =>
+                    {
+                        diagnostics.push(Diagnostic::warning(
+                            &event.code,
+                            Some(part.clone()),
+                            SemanticError::MisplacedBarLine(counter.position().clock),
+                        ));
+                    }
+                    EventKind::Command(command) => {
+                        if let Some(timer_b) =
+                            Self::timer_b(part, &event.code, command, diagnostics)
+                        {
+                            tempo_changes.push(TempoChange {
+                                clock: timeline.clocks,
+                                timer_b,
+                            });
+                        }
+                    }
+                    _ => {}
+                }
+
+                timeline.events.push(TimedEvent {
+                    clock: timeline.clocks,
+                    position: counter.position(),
+                    time: 0.0,
+                    event: event.clone(),
+                });
+                timeline.clocks += event.clocks().unwrap_or(0);
+                counter.advance(event.clocks().unwrap_or(0));
+            }
+        }
+
+        // at the same clock, the later part wins
+        tempo_changes.sort_by_key(|t| t.clock);
+
+        let mut timeline = Self {
+            parts: timelines,
+            tempo_changes,
+        };
+        let times = timeline
+            .parts
+            .iter()
+            .map(|p| p.events.iter().map(|e| timeline.time_at(e.clock)).collect())
+            .collect::<Vec<Vec<Seconds>>>();
+        for (part, times) in timeline.parts.iter_mut().zip(times) {
+            for (event, time) in part.events.iter_mut().zip(times) {
+                event.time = time;
+            }
+        }
+
+        timeline
+    }
+
+    fn timer_b(
+        part: &PartSymbol,
+        code: &Code,
+        command: &PartCommand,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) -> Option<u8> {
+        let (tempo, min, max) = match command {
+            PartCommand::Tempo1(t) => (t, MIN_TEMPO, u8::MAX),
+            PartCommand::Tempo2(t) => (t, 0, MAX_TIMER_B),
+            _ => return None,
+        };
+
+        if !(min..=max).contains(&tempo.value) {
+            diagnostics.push(Diagnostic::error(
+                code,
+                Some(part.clone()),
+                SemanticError::OutOfRange(
+                    tempo.command.clone(),
+                    tempo.value as i32,
+                    min as i32,
+                    max as i32,
+                ),
+            ));
+        }
+
+        Some(match command {
+            PartCommand::Tempo1(_) => tempo_to_timer_b(tempo.value),
+            _ => tempo.value.min(MAX_TIMER_B),
+        })
+    }
+
+    // sets the measure of the diagnostics of parts, from the first event
+    // played at or after where they point
+    pub fn locate(&self, diagnostics: &mut [Diagnostic]) {
+        for diagnostic in diagnostics.iter_mut() {
+            let Some(part) = self
+                .parts
+                .iter()
+                .find(|p| Some(&p.part) == diagnostic.part.as_ref())
+            else {
+                continue;
+            };
+
+            diagnostic.measure = part
+                .events
+                .iter()
+                .find(|e| e.event.code >= diagnostic.code)
+                .map(|e| e.position.measure);
+        }
+    }
+
+    // the tempo in effect at `clock`, a change at the same clock applies
+    fn tempo_index(&self, clock: Clock) -> usize {
+        self.tempo_changes
+            .partition_point(|t| t.clock <= clock)
+            .saturating_sub(1)
+    }
+
+    pub fn time_at(&self, clock: Clock) -> Seconds {
+        let mut time = 0.0;
+        for (i, change) in self.tempo_changes.iter().enumerate() {
+            if change.clock >= clock {
+                break;
+            }
+
+            let end = self
+                .tempo_changes
+                .get(i + 1)
+                .map_or(clock, |next| next.clock.min(clock));
+            time += (end - change.clock) as Seconds * clock_seconds(change.timer_b);
+        }
+
+        time
+    }
+
+    pub fn clock_at(&self, time: Seconds) -> Clock {
+        let mut elapsed = 0.0;
+        for (i, change) in self.tempo_changes.iter().enumerate() {
+            let seconds = clock_seconds(change.timer_b);
+            let clocks = match self.tempo_changes.get(i + 1) {
+                Some(next) => next.clock - change.clock,
+                None => {
+                    return change.clock + ((time - elapsed) / seconds).floor().max(0.0) as Clock;
+                }
+            };
+
+            let span = clocks as Seconds * seconds;
+            if elapsed + span > time {
+                return change.clock + ((time - elapsed) / seconds).floor().max(0.0) as Clock;
+            }
+            elapsed += span;
+        }
+
+        0
+    }
+
+    pub fn clocks(&self) -> Clock {
+        self.parts.iter().map(|p| p.clocks).max().unwrap_or(0)
+    }
+
+    pub fn seconds(&self) -> Seconds {
+        self.time_at(self.clocks())
+    }
+
+    pub fn timer_b_at(&self, clock: Clock) -> u8 {
+        self.tempo_changes[self.tempo_index(clock)].timer_b
+    }
+
+    // the note or rest of each part sounding at `time`
+    pub fn at(&self, time: Seconds) -> Vec<(&PartSymbol, &TimedEvent)> {
+        let clock = self.clock_at(time);
+
+        self.parts
+            .iter()
+            .filter_map(|p| {
+                p.events
+                    .iter()
+                    .find(|e| {
+                        e.event.clocks().is_some_and(|c| c > 0)
+                            && e.clock <= clock
+                            && clock < e.end_clock()
+                    })
+                    .map(|e| (&p.part, e))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pitch::Pitch;
+
+    use super::*;
+
+    fn timeline(mml: &str) -> (Timeline, Vec<Diagnostic>) {
+        let pass2 = crate::parse("", mml).unwrap();
+
+        Timeline::new(&pass2, &CompileOptions::default())
+    }
+
+    fn pitch(event: &TimedEvent) -> Option<Pitch> {
+        match event.event.kind {
+            EventKind::Note { pitch, .. } => pitch,
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_tempo_to_timer_b() {
+        // 60 / (48 * 120) = 10.4ms, 36 steps of Timer-B
+        assert_eq!(220, tempo_to_timer_b(120));
+        assert_eq!(15, tempo_to_timer_b(MIN_TEMPO));
+        assert_eq!(239, tempo_to_timer_b(255));
+        assert!((clock_seconds(220) - 36.0 * TIMER_B_STEP).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_timeline_clock() {
+        let (timeline, diagnostics) = timeline("A c4 [d8 )]2 e4\nB r2\nA f4");
+        assert!(diagnostics.is_empty());
+
+        let a = &timeline.parts[0];
+        assert_eq!(PartSymbol::A, a.part);
+        assert_eq!(
+            vec![0, 24, 36, 36, 48, 48, 72],
+            a.events.iter().map(|e| e.clock).collect::<Vec<Clock>>()
+        );
+        assert_eq!(96, a.clocks);
+        assert_eq!(
+            vec![Some(101), Some(101), Some(105), Some(108), Some(108)],
+            a.events
+                .iter()
+                .filter_map(|e| match e.event.kind {
+                    EventKind::Note { volume, .. } => Some(volume),
+                    _ => None,
+                })
+                .collect::<Vec<Option<u8>>>()
+        );
+        assert_eq!(48, timeline.parts[1].clocks);
+        assert_eq!(96, timeline.clocks());
+    }
+
+    #[test]
+    fn test_timeline_tempo() {
+        let (timeline, diagnostics) = timeline("#Tempo 120\nA c2 d2\nB r2 T200 r4 t10");
+        assert_eq!(1, diagnostics.len());
+        assert_eq!(
+            SemanticError::OutOfRange("t".to_string(), 10, 18, 255),
+            diagnostics[0].error
+        );
+
+        assert_eq!(220, timeline.timer_b_at(0));
+        assert_eq!(200, timeline.timer_b_at(48));
+        assert_eq!(
+            vec![0, 48, 72],
+            timeline
+                .tempo_changes
+                .iter()
+                .map(|t| t.clock)
+                .collect::<Vec<Clock>>()
+        );
+
+        let first = 48.0 * clock_seconds(220);
+        assert!((timeline.parts[0].events[1].time - first).abs() < 1e-9);
+        assert!((timeline.time_at(60) - (first + 12.0 * clock_seconds(200))).abs() < 1e-9);
+        assert_eq!(60, timeline.clock_at(timeline.time_at(60) + 1e-9));
+
+        let playing = timeline.at(timeline.time_at(50));
+        assert_eq!(2, playing.len());
+        assert_eq!(Some(62), pitch(playing[0].1));
+        assert_eq!(&PartSymbol::B, playing[1].0);
+        assert_eq!(48, playing[1].1.clock);
+    }
+
+    #[test]
+    fn test_timeline_measure() {
+        let (timeline, diagnostics) = timeline("A c2 c2 | c4 | c2.\nB C48 c2 c2 | c1 |B c4");
+        assert_eq!(1, diagnostics.len());
+        assert_eq!(SemanticError::MisplacedBarLine(24), diagnostics[0].error);
+        assert_eq!(Some(2), diagnostics[0].measure);
+        assert!(diagnostics[0].to_string().contains("part A, measure 2: "));
+
+        let a = &timeline.parts[0].events;
+        assert_eq!(
+            Position {
+                measure: 2,
+                clock: 24
+            },
+            a[5].position
+        );
+        assert_eq!(
+            Position {
+                measure: 2,
+                clock: 0
+            },
+            timeline.parts[1].events[4].position
+        );
+    }
+}
@@ -0,0 +1,163 @@
+use crate::{
+    commands::commands_loop::DEFAULT_LOOP_COUNT,
+    diagnostics::Diagnostic,
+    errors::SemanticError,
+    events::{Event, EventKind, PartEvents},
+    models::PartSymbol,
+};
+
+// flattens [ ... : ... ] into the sequence the driver plays
+#[derive(Debug, Default)]
+pub struct LoopUnroller;
+
+impl LoopUnroller {
+    pub fn new() -> Self {
+        Self
+    }
+
+    pub fn unroll(&self, parts: &[PartEvents]) -> (Vec<PartEvents>, Vec<Diagnostic>) {
+        let mut diagnostics = vec![];
+        let parts = parts
+            .iter()
+            .map(|(part, events)| {
+                let mut unrolled = vec![];
+                Self::unroll_events(part, events, &mut unrolled, &mut diagnostics);
+                (part.clone(), unrolled)
+            })
+            .collect();
+
+        (parts, diagnostics)
+    }
+
+    // false after an infinite loop, nothing behind it is played
+    fn unroll_events(
+        part: &PartSymbol,
+        events: &[Event],
+        unrolled: &mut Vec<Event>,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) -> bool {
+        for event in events {
+            let EventKind::Loop {
+                count,
+                body_pre,
+                body_post,
+            } = &event.kind
+            else {
+                unrolled.push(event.clone());
+                continue;
+            };
+
+            let count = count.unwrap_or(DEFAULT_LOOP_COUNT);
+            if count == 0 {
+                diagnostics.push(Diagnostic::warning(
+                    &event.code,
+                    Some(part.clone()),
+                    SemanticError::InfiniteLoop,
+                ));
+
+                // one pass is enough to tell what is played
+                if Self::unroll_events(part, body_pre, unrolled, diagnostics) {
+                    Self::unroll_events(part, body_post, unrolled, diagnostics);
+                }
+                return false;
+            }
+
+            for i in 0..count {
+                if !Self::unroll_events(part, body_pre, unrolled, diagnostics) {
+                    return false;
+                }
+
+                // : leaves the loop on the last pass
+                if i + 1 < count && !Self::unroll_events(part, body_post, unrolled, diagnostics) {
+                    return false;
+                }
+            }
+        }
+
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        commands::commands_mml::NoteR, events::Clock, meta_models::Code,
+        part_command::PartCommandStruct,
+    };
+
+    use super::*;
+
+    fn rest(clocks: Clock) -> Event {
+        let command = NoteR {
+            command: "r".to_string(),
+            length: None,
+            dots: 0,
+        };
+        Event::new(
+            &Code::default(),
+            EventKind::Rest {
+                command: command.to_variant(),
+                clocks,
+            },
+        )
+    }
+
+    fn repeat(count: Option<u8>, body_pre: Vec<Event>, body_post: Vec<Event>) -> Event {
+        Event::new(
+            &Code::default(),
+            EventKind::Loop {
+                count,
+                body_pre,
+                body_post,
+            },
+        )
+    }
+
+    fn unroll(events: Vec<Event>) -> (Vec<Clock>, Vec<Diagnostic>) {
+        let (parts, diagnostics) = LoopUnroller::new().unroll(&[(PartSymbol::A, events)]);
+        let clocks = parts[0].1.iter().filter_map(|e| e.clocks()).collect();
+        (clocks, diagnostics)
+    }
+
+    #[test]
+    fn test_unroll() {
+        // [1 [2 3]3 : 4] 5
+        let (clocks, diagnostics) = unroll(vec![
+            repeat(
+                None,
+                vec![rest(1), repeat(Some(3), vec![rest(2), rest(3)], vec![])],
+                vec![rest(4)],
+            ),
+            rest(5),
+        ]);
+
+        assert!(diagnostics.is_empty());
+        assert_eq!(vec![1, 2, 3, 2, 3, 2, 3, 4, 1, 2, 3, 2, 3, 2, 3, 5], clocks);
+    }
+
+    #[test]
+    fn test_unroll_infinite() {
+        // 1 [2 [3 : 4]0 5]3 6
+        let (clocks, diagnostics) = unroll(vec![
+            rest(1),
+            repeat(
+                Some(3),
+                vec![
+                    rest(2),
+                    repeat(Some(0), vec![rest(3)], vec![rest(4)]),
+                    rest(5),
+                ],
+                vec![],
+            ),
+            rest(6),
+        ]);
+
+        assert_eq!(vec![1, 2, 3, 4], clocks);
+        assert_eq!(1, diagnostics.len());
+        assert!(!diagnostics[0].is_error());
+        assert_eq!(SemanticError::InfiniteLoop, diagnostics[0].error);
+
+        let (_, diagnostics) = unroll(vec![repeat(Some(0), vec![], vec![])]);
+        assert_eq!(Some(PartSymbol::A), diagnostics[0].part);
+    }
+}
@@ -3,6 +3,7 @@ use crate::meta_models::{Code, Command};
 const SEPARATORS: &[char] = &[' ', '\t', '\n', '\r'];
 const DELIMITERS: &[char] = &['\n', '\0'];
 
+#[allow(dead_code)]
 pub fn split(target: &str) -> Vec<&str> {
     target
         .split(SEPARATORS)
@@ -19,6 +20,7 @@ pub fn is_n(c: char) -> bool {
 }
 
 pub trait ParseUtil {
+    #[allow(dead_code)]
     fn get_mml(&self) -> &String;
 
     fn get_code(&self) -> &Code;
@@ -27,6 +29,7 @@ pub trait ParseUtil {
         self.get_code().clone()
     }
 
+    #[allow(dead_code)]
     fn current_line(&self) -> String {
         let mml = self.get_mml();
         let lines = mml.lines().collect::<Vec<&str>>();
@@ -41,6 +44,7 @@ pub fn get_type_name<T>() -> &'static str {
     full.rsplit("::").next().unwrap()
 }
 
+#[allow(dead_code)]
 pub fn some_vec<T>(vec: Vec<T>) -> Option<Vec<T>> {
     if vec.is_empty() { None } else { Some(vec) }
 }
@@ -0,0 +1,483 @@
+use crate::{
+    commands::commands_tone::ToneNumber,
+    diagnostics::Diagnostic,
+    errors::SemanticError,
+    meta_models::{Code, Pass2Result},
+    models::{Chip, Fm3ExtendMacro, PartSymbol, PpzExtendMacro},
+    options::CompileOptions,
+    part_command::{Availability, PartCommand, WrappedPartCommand},
+    target::{PcmKind, TargetDriver, TargetProfile},
+};
+
+pub struct Validator<'a> {
+    pass2: &'a Pass2Result,
+    target: &'a TargetProfile,
+}
+
+impl<'a> Validator<'a> {
+    pub fn new(pass2: &'a Pass2Result, options: &'a CompileOptions) -> Self {
+        Self {
+            pass2,
+            target: &options.target,
+        }
+    }
+
+    pub fn validate(&self) -> Vec<Diagnostic> {
+        let mut diagnostics = self.pass2.diagnostics.clone();
+
+        for (part, commands) in &self.pass2.parts {
+            let chip = part.chip();
+            if let Some(first) = commands.first()
+                && !self.is_chip_available(chip, false)
+            {
+                diagnostics.push(Diagnostic::error(
+                    first.code(),
+                    Some(part.clone()),
+                    SemanticError::UnavailablePart(part.clone(), self.target.driver),
+                ));
+                continue;
+            }
+
+            self.validate_commands(Some(part), chip, commands, &mut diagnostics);
+        }
+
+        let fm3_extend = self
+            .pass2
+            .find_macros("FM3Extend")
+            .last()
+            .map(|m| Fm3ExtendMacro::from((*m).clone()));
+        let ppz_extend = self
+            .pass2
+            .find_macros("PPZExtend")
+            .last()
+            .map(|m| PpzExtendMacro::from((*m).clone()));
+
+        for (part, commands) in &self.pass2.extend_parts {
+            let Some(first) = commands.first() else {
+                continue;
+            };
+
+            let mut part_diagnostics = vec![];
+            match part.chip(fm3_extend.as_ref(), ppz_extend.as_ref()) {
+                None => part_diagnostics.push(Diagnostic::error(
+                    first.code(),
+                    None,
+                    SemanticError::UndeclaredExtendPart(part.clone()),
+                )),
+                Some(chip) if !self.is_chip_available(chip, true) => {
+                    part_diagnostics.push(Diagnostic::error(
+                        first.code(),
+                        None,
+                        SemanticError::UnavailableExtendPart(part.clone(), self.target.driver),
+                    ))
+                }
+                Some(chip) => self.validate_commands(None, chip, commands, &mut part_diagnostics),
+            }
+
+            diagnostics.extend(
+                part_diagnostics
+                    .into_iter()
+                    .map(|d| d.in_extend_part(part.clone())),
+            );
+        }
+
+        diagnostics
+    }
+
+    // the PCM of the extended parts is PPZ8
+    fn is_chip_available(&self, chip: Chip, extend: bool) -> bool {
+        match chip {
+            Chip::Pcm if extend => self.target.driver == TargetDriver::PmdPpzE,
+            Chip::Pcm => self.target.driver.has_pcm(),
+            Chip::RhythmSelect => self.target.driver.has_rhythm(),
+            _ => true,
+        }
+    }
+
+    fn validate_commands(
+        &self,
+        part: Option<&PartSymbol>,
+        chip: Chip,
+        commands: &[WrappedPartCommand],
+        diagnostics: &mut Vec<Diagnostic>,
+    ) {
+        for command in commands {
+            let data = command.data();
+            match data.availability(&chip) {
+                Availability::Supported => {}
+                Availability::Differs => diagnostics.push(Diagnostic::warning(
+                    command.code(),
+                    part.cloned(),
+                    SemanticError::DifferentBehaviour(data.command_name(), chip),
+                )),
+                Availability::Unsupported => {
+                    diagnostics.push(Diagnostic::error(
+                        command.code(),
+                        part.cloned(),
+                        SemanticError::UnsupportedCommand(data.command_name(), chip),
+                    ));
+                    continue;
+                }
+            }
+
+            self.validate_target(part, chip, command, diagnostics);
+
+            if let PartCommand::LocalLoop(l) = data {
+                self.validate_commands(part, chip, &l.body_pre, diagnostics);
+                self.validate_commands(part, chip, &l.body_post, diagnostics);
+            }
+        }
+    }
+
+    fn validate_target(
+        &self,
+        part: Option<&PartSymbol>,
+        chip: Chip,
+        command: &WrappedPartCommand,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) {
+        let driver = self.target.driver;
+        let unsupported = || {
+            Diagnostic::error(
+                command.code(),
+                part.cloned(),
+                SemanticError::UnsupportedOnTarget(command.data().command_name(), driver),
+            )
+        };
+
+        match command.data() {
+            PartCommand::PanEx(_) if !driver.supports_pan_ex() => {
+                diagnostics.push(unsupported());
+            }
+            // [音源] FM / SSG / PCM(AD,PPZ)
+            PartCommand::Alpeggio(_)
+                if chip == Chip::Pcm && driver.pcm_kind() == Some(PcmKind::Pcm86) =>
+            {
+                diagnostics.push(unsupported());
+            }
+            PartCommand::ToneNumber(t) => {
+                self.validate_tone_number(part, chip, command.code(), t, diagnostics);
+            }
+            _ => {}
+        }
+    }
+
+    fn validate_tone_number(
+        &self,
+        part: Option<&PartSymbol>,
+        chip: Chip,
+        code: &Code,
+        tone: &ToneNumber,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) {
+        let number = tone.tone_number();
+        let max = match chip {
+            Chip::Ssg => 9,
+            Chip::RhythmDefine => 16383,
+            _ => 255,
+        };
+
+        if number > max {
+            diagnostics.push(Diagnostic::error(
+                code,
+                part.cloned(),
+                SemanticError::OutOfRange(tone.command.clone(), number as i32, 0, max as i32),
+            ));
+            return;
+        }
+
+        if chip == Chip::RhythmDefine && number > self.target.max_ssg_rhythm_tone() {
+            diagnostics.push(Diagnostic::warning(
+                code,
+                part.cloned(),
+                SemanticError::RequiresPpsdrv(number),
+            ));
+        }
+
+        if chip != Chip::Pcm {
+            return;
+        }
+
+        let (min, max) = self.target.pcm_repeat_address_range();
+        for value in [tone.repeat_start, tone.repeat_end, tone.release_start]
+            .into_iter()
+            .flatten()
+        {
+            if value < min || value > max {
+                diagnostics.push(Diagnostic::error(
+                    code,
+                    part.cloned(),
+                    SemanticError::OutOfRange(tone.command.clone(), value, min, max),
+                ));
+            }
+        }
+
+        if tone.release_start.is_some() && !self.target.driver.supports_pcm_release() {
+            diagnostics.push(Diagnostic::warning(
+                code,
+                part.cloned(),
+                SemanticError::IgnoredOnTarget(tone.command.clone(), self.target.driver),
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        commands::{
+            commands_envelope::SsgPcmSoftwareEnvelope, commands_loop::LocalLoop, commands_mml::Note,
+        },
+        diagnostics::Severity,
+        meta_models::Code,
+        models::{Chip, ExtendPartSymbol, Fm3ExtendMacro, PpzExtendMacro},
+        part_command::PartCommandStruct,
+        target::TargetDriver,
+    };
+
+    use super::*;
+
+    fn note(command: &str) -> PartCommand {
+        Note {
+            command: command.to_string(),
+            natural: false,
+            semitone: None,
+            length: None,
+            dots: 0,
+        }
+        .to_variant()
+    }
+
+    fn envelope() -> PartCommand {
+        SsgPcmSoftwareEnvelope {
+            command: "E".to_string(),
+            value1: 1,
+            value2: -2,
+            value3: 24,
+            value4: 1,
+            value5: None,
+            value6: None,
+        }
+        .to_variant()
+    }
+
+    #[test]
+    fn test_part_chip() {
+        assert_eq!(Chip::Fm, PartSymbol::A.chip());
+        assert_eq!(Chip::Fm, PartSymbol::F.chip());
+        assert_eq!(Chip::Ssg, PartSymbol::G.chip());
+        assert_eq!(Chip::Ssg, PartSymbol::I.chip());
+        assert_eq!(Chip::Pcm, PartSymbol::J.chip());
+        assert_eq!(Chip::RhythmSelect, PartSymbol::K.chip());
+        assert_eq!(Chip::RhythmDefine, PartSymbol::R.chip());
+
+        let fm3 = Fm3ExtendMacro {
+            code: Code::default(),
+            value: vec![ExtendPartSymbol::X, ExtendPartSymbol::Y],
+        };
+        let ppz = PpzExtendMacro {
+            code: Code::default(),
+            value: vec![ExtendPartSymbol::a],
+        };
+        assert_eq!(
+            Some(Chip::Fm),
+            ExtendPartSymbol::X.chip(Some(&fm3), Some(&ppz))
+        );
+        assert_eq!(
+            Some(Chip::Pcm),
+            ExtendPartSymbol::a.chip(Some(&fm3), Some(&ppz))
+        );
+        assert_eq!(None, ExtendPartSymbol::b.chip(Some(&fm3), Some(&ppz)));
+        assert_eq!(None, ExtendPartSymbol::X.chip(None, None));
+    }
+
+    #[test]
+    fn test_validate_envelope() {
+        let code = Code {
+            file_name: "".to_string(),
+            lines: 3,
+            chars: 2,
+        };
+
+        let mut pass2 = Pass2Result::default();
+        pass2.parts.push((
+            PartSymbol::G,
+            vec![WrappedPartCommand::new(&code, envelope())],
+        ));
+        pass2.parts.push((
+            PartSymbol::A,
+            vec![WrappedPartCommand::new(&code, envelope())],
+        ));
+        pass2.parts.push((
+            PartSymbol::K,
+            vec![WrappedPartCommand::new(&code, envelope())],
+        ));
+
+        let diagnostics = Validator::new(&pass2, &CompileOptions::default()).validate();
+        assert_eq!(2, diagnostics.len());
+
+        assert_eq!(Severity::Warning, diagnostics[0].severity);
+        assert_eq!(Some(PartSymbol::A), diagnostics[0].part);
+        assert_eq!(code, diagnostics[0].code);
+        assert_eq!(
+            SemanticError::DifferentBehaviour("E".to_string(), Chip::Fm),
+            diagnostics[0].error
+        );
+
+        assert_eq!(Severity::Error, diagnostics[1].severity);
+        assert_eq!(Some(PartSymbol::K), diagnostics[1].part);
+        assert_eq!(
+            SemanticError::UnsupportedCommand("E".to_string(), Chip::RhythmSelect),
+            diagnostics[1].error
+        );
+    }
+
+    #[test]
+    fn test_validate_inside_loop() {
+        let code = Code::default();
+        let local_loop = LocalLoop {
+            begin_command: "[".to_string(),
+            body_pre: vec![WrappedPartCommand::new(&code, note("c"))],
+            separator: None,
+            body_post: vec![],
+            end_command: "]".to_string(),
+            count: Some(2),
+            effective_count: 2,
+        };
+
+        let mut pass2 = Pass2Result::default();
+        pass2.parts.push((
+            PartSymbol::K,
+            vec![WrappedPartCommand::new(
+                &code,
+                local_loop.clone().to_variant(),
+            )],
+        ));
+        pass2.parts.push((
+            PartSymbol::R,
+            vec![WrappedPartCommand::new(&code, local_loop.to_variant())],
+        ));
+
+        let diagnostics = Validator::new(&pass2, &CompileOptions::default()).validate();
+        assert_eq!(1, diagnostics.len());
+        assert_eq!(Some(PartSymbol::K), diagnostics[0].part);
+        assert_eq!(
+            SemanticError::UnsupportedCommand("c".to_string(), Chip::RhythmSelect),
+            diagnostics[0].error
+        );
+    }
+
+    #[test]
+    fn test_validate_target_pcm() {
+        let pass2 = crate::parse("", "J\t@0,100,-50,-2000 px-2,1 c\n").unwrap();
+
+        let diagnostics = Validator::new(&pass2, &CompileOptions::default()).validate();
+        assert_eq!(1, diagnostics.len());
+        assert_eq!(
+            SemanticError::UnsupportedOnTarget("px".to_string(), TargetDriver::PmdB2),
+            diagnostics[0].error
+        );
+
+        let profile = TargetProfile::new(TargetDriver::Pmd86);
+        assert!(
+            Validator::new(&pass2, &CompileOptions::new(profile.clone()))
+                .validate()
+                .is_empty()
+        );
+
+        let profile = TargetProfile {
+            driver: TargetDriver::Pmd86,
+            ppsdrv: false,
+            adpcm_compatible: true,
+            volume_down: None,
+        };
+        let diagnostics = Validator::new(&pass2, &CompileOptions::new(profile.clone())).validate();
+        assert_eq!(1, diagnostics.len());
+        assert_eq!(
+            SemanticError::OutOfRange("@".to_string(), -2000, -1024, 1023),
+            diagnostics[0].error
+        );
+
+        let profile = TargetProfile::new(TargetDriver::PmdPpz);
+        let diagnostics = Validator::new(&pass2, &CompileOptions::new(profile.clone())).validate();
+        assert_eq!(1, diagnostics.len());
+        assert_eq!(Severity::Warning, diagnostics[0].severity);
+        assert_eq!(
+            SemanticError::IgnoredOnTarget("@".to_string(), TargetDriver::PmdPpz),
+            diagnostics[0].error
+        );
+
+        let profile = TargetProfile::new(TargetDriver::Pmd);
+        let diagnostics = Validator::new(&pass2, &CompileOptions::new(profile.clone())).validate();
+        assert_eq!(1, diagnostics.len());
+        assert_eq!(
+            SemanticError::UnavailablePart(PartSymbol::J, TargetDriver::Pmd),
+            diagnostics[0].error
+        );
+    }
+
+    #[test]
+    fn test_validate_target_ssg_rhythm() {
+        let pass2 = crate::parse("", "R\t@2 c @4096 c\n").unwrap();
+
+        let diagnostics = Validator::new(&pass2, &CompileOptions::default()).validate();
+        assert_eq!(1, diagnostics.len());
+        assert_eq!(Severity::Warning, diagnostics[0].severity);
+        assert_eq!(SemanticError::RequiresPpsdrv(4096), diagnostics[0].error);
+
+        let profile = TargetProfile {
+            driver: TargetDriver::PmdB2,
+            ppsdrv: true,
+            adpcm_compatible: false,
+            volume_down: None,
+        };
+        assert!(
+            Validator::new(&pass2, &CompileOptions::new(profile.clone()))
+                .validate()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_validate_extend_parts() {
+        let pass2 = crate::parse(
+            "",
+            "#FM3Extend XY\n#PPZExtend a\nX E1,-2,24,1 c\na @0,100,-50,-2000 c\nb c\n",
+        )
+        .unwrap();
+        assert_eq!(3, pass2.extend_parts.len());
+
+        let diagnostics = Validator::new(&pass2, &CompileOptions::default()).validate();
+        assert_eq!(
+            vec![
+                (
+                    Some(ExtendPartSymbol::X),
+                    SemanticError::DifferentBehaviour("E".to_string(), Chip::Fm)
+                ),
+                (
+                    Some(ExtendPartSymbol::a),
+                    SemanticError::UnavailableExtendPart(ExtendPartSymbol::a, TargetDriver::PmdB2)
+                ),
+                (
+                    Some(ExtendPartSymbol::b),
+                    SemanticError::UndeclaredExtendPart(ExtendPartSymbol::b)
+                ),
+            ],
+            diagnostics
+                .into_iter()
+                .map(|d| (d.extend_part, d.error))
+                .collect::<Vec<_>>()
+        );
+
+        // PPZ8 plays the extended PCM parts, it has no release of @
+        let profile = TargetProfile::new(TargetDriver::PmdPpzE);
+        let diagnostics = Validator::new(&pass2, &CompileOptions::new(profile)).validate();
+        assert_eq!(3, diagnostics.len());
+        assert_eq!(Some(ExtendPartSymbol::a), diagnostics[1].extend_part);
+        assert_eq!(None, diagnostics[1].part);
+        assert_eq!(
+            SemanticError::IgnoredOnTarget("@".to_string(), TargetDriver::PmdPpzE),
+            diagnostics[1].error
+        );
+    }
+}
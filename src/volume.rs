@@ -0,0 +1,450 @@
+use crate::{
+    diagnostics::Diagnostic,
+    errors::SemanticError,
+    events::{Event, EventKind, PartEvents},
+    meta_models::Pass2Result,
+    models::{
+        Chip, ExtendNormalOption, InstrumentsCategorySymbol, PartSymbol, PcmVolumeMacro,
+        RelativeAbsolute8, VolumeDownMacro,
+    },
+    options::CompileOptions,
+    part_command::PartCommand,
+    target::TargetProfile,
+};
+
+// V of FM v0 .. v16
+const FM_VOLUME: [u8; 17] = [
+    85, 87, 90, 93, 95, 98, 101, 103, 106, 109, 111, 114, 117, 119, 122, 125, 127,
+];
+
+// volume down of each sound source, the driver lowers the volume of a part
+// by value/256 of it
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct VolumeDown {
+    pub fm: u8,
+    pub ssg: u8,
+    pub pcm: u8,
+    pub rhythm: u8,
+}
+
+impl VolumeDown {
+    // #VolumeDown, put at the head of the G part as DF DS DP DR.
+    // ± values are relative to the options of the driver, + lowers the volume
+    pub fn resolve(pass2: &Pass2Result, target: &TargetProfile) -> Self {
+        let base = target.driver_volume_down();
+        let mut volume_down = base;
+
+        for m in pass2.find_macros("VolumeDown") {
+            for (category, value) in VolumeDownMacro::from(m.clone()).value {
+                let value = match value {
+                    RelativeAbsolute8::Absolute(value) => value,
+                    RelativeAbsolute8::Relative(delta) => {
+                        (base.get(&category) as i16 + delta).clamp(0, u8::MAX as i16) as u8
+                    }
+                };
+                volume_down.set(&category, value);
+            }
+        }
+
+        volume_down
+    }
+
+    pub fn get(&self, category: &InstrumentsCategorySymbol) -> u8 {
+        match category {
+            InstrumentsCategorySymbol::F => self.fm,
+            InstrumentsCategorySymbol::S => self.ssg,
+            InstrumentsCategorySymbol::P => self.pcm,
+            InstrumentsCategorySymbol::R => self.rhythm,
+        }
+    }
+
+    pub fn set(&mut self, category: &InstrumentsCategorySymbol, value: u8) {
+        match category {
+            InstrumentsCategorySymbol::F => self.fm = value,
+            InstrumentsCategorySymbol::S => self.ssg = value,
+            InstrumentsCategorySymbol::P => self.pcm = value,
+            InstrumentsCategorySymbol::R => self.rhythm = value,
+        }
+    }
+
+    pub fn of_chip(&self, chip: &Chip) -> u8 {
+        match chip {
+            Chip::Fm => self.fm,
+            Chip::Ssg => self.ssg,
+            Chip::Pcm => self.pcm,
+            Chip::RhythmSelect | Chip::RhythmDefine => self.rhythm,
+        }
+    }
+
+    // volume of the chip as the driver writes it
+    pub fn attenuate(&self, chip: &Chip, volume: u8) -> u8 {
+        let down = self.of_chip(chip) as u32;
+        (volume as u32 * (256 - down) / 256) as u8
+    }
+}
+
+// volume of a part in V, before the volume down of the driver.
+// R定義 parts play the SSG drums and take the SSG range
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VolumeState {
+    pub chip: Chip,
+    // #PCMVolume Extend, V = v×v on PCM parts
+    pub pcm_extend: bool,
+    pub volume: u8,
+    // v+ v-, added to each following v
+    pub v_offset: i16,
+    // v) v(, added to each following V
+    pub volume_offset: i16,
+}
+
+impl VolumeState {
+    pub fn new(chip: Chip, pcm_extend: bool) -> Self {
+        // set by the driver at the head of each part
+        let volume = match chip {
+            Chip::Fm => 108,
+            Chip::Ssg | Chip::RhythmSelect | Chip::RhythmDefine => 8,
+            Chip::Pcm => 128,
+        };
+
+        Self {
+            chip,
+            pcm_extend,
+            volume,
+            v_offset: 0,
+            volume_offset: 0,
+        }
+    }
+
+    pub fn max_v(&self) -> u8 {
+        match self.chip {
+            Chip::Fm | Chip::Pcm => 16,
+            Chip::Ssg | Chip::RhythmSelect | Chip::RhythmDefine => 15,
+        }
+    }
+
+    pub fn max_volume(&self) -> u8 {
+        match self.chip {
+            Chip::Fm => 127,
+            Chip::Ssg | Chip::RhythmSelect | Chip::RhythmDefine => 15,
+            Chip::Pcm => 255,
+        }
+    }
+
+    pub fn v_to_volume(&self, v: u8) -> u8 {
+        let v = v.min(self.max_v());
+        match self.chip {
+            Chip::Fm => FM_VOLUME[v as usize],
+            Chip::Pcm if self.pcm_extend => (v as u16 * v as u16).min(255) as u8,
+            Chip::Pcm => (v as u16 * 16).min(255) as u8,
+            Chip::Ssg | Chip::RhythmSelect | Chip::RhythmDefine => v,
+        }
+    }
+
+    // one step of v in V, used by ( ) without the value
+    pub fn step(&self) -> u8 {
+        match self.chip {
+            Chip::Fm => 4,
+            Chip::Pcm => 16,
+            Chip::Ssg | Chip::RhythmSelect | Chip::RhythmDefine => 1,
+        }
+    }
+
+    pub fn apply(&mut self, command: &PartCommand) -> Result<(), SemanticError> {
+        match command {
+            PartCommand::Volume1(v) => {
+                Self::check(&v.command, v.value, self.max_v())?;
+                let value = (v.value as i16 + self.v_offset).clamp(0, self.max_v() as i16);
+                self.set(self.v_to_volume(value as u8) as i16 + self.volume_offset);
+            }
+            PartCommand::Volume2(v) => {
+                Self::check(&v.command, v.value, self.max_volume())?;
+                self.set(v.value as i16 + self.volume_offset);
+            }
+            PartCommand::GlobalVolume1Positive(v) => self.v_offset += v.value as i16,
+            PartCommand::GlobalVolume1Negative(v) => self.v_offset -= v.value as i16,
+            PartCommand::GlobalVolume2Positive(v) => self.volume_offset += v.value as i16,
+            PartCommand::GlobalVolume2Negative(v) => self.volume_offset -= v.value as i16,
+            PartCommand::RelativeVolumeUp(r) => {
+                self.shift(r.value.unwrap_or(self.step()) as i16);
+            }
+            PartCommand::RelativeVolumeDown(r) => {
+                self.shift(-(r.value.unwrap_or(self.step()) as i16));
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    // the driver stops at the ends of the range
+    pub fn shift(&mut self, delta: i16) {
+        self.set(self.volume as i16 + delta);
+    }
+
+    fn set(&mut self, volume: i16) {
+        self.volume = volume.clamp(0, self.max_volume() as i16) as u8;
+    }
+
+    fn check(command: &str, value: u8, max: u8) -> Result<(), SemanticError> {
+        if value > max {
+            return Err(SemanticError::OutOfRange(
+                command.to_string(),
+                value as i32,
+                0,
+                max as i32,
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+// sets the volume of each note, lowered by the volume down as the driver
+// writes it. the state runs through the loops once, give unrolled events
+// when ( ) inside a loop should count on every pass
+pub struct VolumeResolver<'a> {
+    pass2: &'a Pass2Result,
+    volume_down: VolumeDown,
+}
+
+impl<'a> VolumeResolver<'a> {
+    pub fn new(pass2: &'a Pass2Result, options: &CompileOptions) -> Self {
+        Self {
+            pass2,
+            volume_down: VolumeDown::resolve(pass2, &options.target),
+        }
+    }
+
+    pub fn pcm_extend(&self) -> bool {
+        self.pass2
+            .find_macros("PCMVolume")
+            .last()
+            .map(|m| PcmVolumeMacro::from((*m).clone()).value == ExtendNormalOption::Extend)
+            .unwrap_or(false)
+    }
+
+    pub fn resolve(&self, parts: &mut [PartEvents]) -> Vec<Diagnostic> {
+        let pcm_extend = self.pcm_extend();
+        let mut states: Vec<(PartSymbol, VolumeState)> = vec![];
+        let mut diagnostics = vec![];
+
+        for (part, events) in parts.iter_mut() {
+            // the rhythm part is played by the volume of the rhythm sounds
+            if part.chip() == Chip::RhythmSelect {
+                continue;
+            }
+
+            // a part written over several lines continues its state
+            let index = match states.iter().position(|(s, _)| s == part) {
+                Some(index) => index,
+                None => {
+                    states.push((part.clone(), VolumeState::new(part.chip(), pcm_extend)));
+                    states.len() - 1
+                }
+            };
+
+            self.resolve_events(part, events, &mut states[index].1, &mut diagnostics);
+        }
+
+        diagnostics
+    }
+
+    fn resolve_events(
+        &self,
+        part: &PartSymbol,
+        events: &mut [Event],
+        state: &mut VolumeState,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) {
+        for event in events.iter_mut() {
+            match &mut event.kind {
+                EventKind::Note { volume, .. } => {
+                    *volume = Some(self.volume_down.attenuate(&state.chip, state.volume))
+                }
+                EventKind::Loop {
+                    body_pre,
+                    body_post,
+                    ..
+                } => {
+                    self.resolve_events(part, body_pre, state, diagnostics);
+                    self.resolve_events(part, body_post, state, diagnostics);
+                }
+                EventKind::Command(command) => {
+                    if let Err(e) = state.apply(command) {
+                        diagnostics.push(Diagnostic::error(&event.code, Some(part.clone()), e));
+                    }
+                }
+                // {{ }} 数値3
+                EventKind::VolumeShift(delta) => state.shift(*delta as i16),
+                EventKind::Rest { .. } => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        meta_models::{Code, VariantValue},
+        models::Macro,
+        target::TargetDriver,
+    };
+
+    use super::*;
+
+    fn pass2(value: &str) -> Pass2Result {
+        Pass2Result {
+            macros: vec![Macro {
+                code: Code::default(),
+                key: "Volumedown".to_owned(),
+                value: VariantValue::String(value.to_owned()),
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_resolve_volume_down() {
+        let target = TargetProfile::default();
+        assert_eq!(
+            VolumeDown {
+                fm: 16,
+                ..Default::default()
+            },
+            VolumeDown::resolve(&Pass2Result::default(), &target)
+        );
+
+        // relative to the driver, not to the previous value
+        let volume_down = VolumeDown::resolve(&pass2("FR+16,P128,S-32,F-8"), &target);
+        assert_eq!(
+            VolumeDown {
+                fm: 8,
+                ssg: 0,
+                pcm: 128,
+                rhythm: 16,
+            },
+            volume_down
+        );
+
+        let target = TargetProfile {
+            volume_down: Some(VolumeDown {
+                fm: 250,
+                ssg: 40,
+                pcm: 0,
+                rhythm: 0,
+            }),
+            ..TargetProfile::new(TargetDriver::Pmd)
+        };
+        let volume_down = VolumeDown::resolve(&pass2("F+16,S-32"), &target);
+        assert_eq!(255, volume_down.fm);
+        assert_eq!(8, volume_down.ssg);
+        assert_eq!(
+            0,
+            TargetProfile::new(TargetDriver::Pmd)
+                .driver_volume_down()
+                .fm
+        );
+    }
+
+    #[test]
+    fn test_attenuate() {
+        let volume_down = VolumeDown {
+            fm: 16,
+            ssg: 0,
+            pcm: 128,
+            rhythm: 255,
+        };
+        assert_eq!(119, volume_down.attenuate(&Chip::Fm, 127));
+        assert_eq!(15, volume_down.attenuate(&Chip::Ssg, 15));
+        assert_eq!(127, volume_down.attenuate(&Chip::Pcm, 255));
+        assert_eq!(0, volume_down.attenuate(&Chip::RhythmSelect, 63));
+    }
+
+    // PMD has no volume down, the volumes are as set by the commands
+    fn volumes(mml: &str) -> (Vec<Option<u8>>, Vec<SemanticError>) {
+        volumes_on(mml, TargetProfile::new(TargetDriver::Pmd))
+    }
+
+    fn volumes_on(mml: &str, target: TargetProfile) -> (Vec<Option<u8>>, Vec<SemanticError>) {
+        let pass2 = crate::parse("", mml).unwrap();
+
+        let options = CompileOptions::new(target);
+        let (mut parts, _) = crate::length::LengthResolver::new(&pass2, &options).resolve();
+        let errors = VolumeResolver::new(&pass2, &options).resolve(&mut parts);
+
+        let volumes = parts
+            .iter()
+            .flat_map(|(_, events)| events.iter())
+            .filter_map(|e| match e.kind {
+                EventKind::Note { volume, .. } => Some(volume),
+                _ => None,
+            })
+            .collect();
+        (volumes, errors.into_iter().map(|d| d.error).collect())
+    }
+
+    #[test]
+    fn test_v_to_volume() {
+        let fm = VolumeState::new(Chip::Fm, false);
+        assert_eq!(85, fm.v_to_volume(0));
+        assert_eq!(127, fm.v_to_volume(16));
+
+        let pcm = VolumeState::new(Chip::Pcm, false);
+        assert_eq!(192, pcm.v_to_volume(12));
+        assert_eq!(255, pcm.v_to_volume(16));
+        let pcm = VolumeState::new(Chip::Pcm, true);
+        assert_eq!(144, pcm.v_to_volume(12));
+
+        assert_eq!(12, VolumeState::new(Chip::Ssg, true).v_to_volume(12));
+    }
+
+    #[test]
+    fn test_resolve_volume() {
+        let (volumes, errors) =
+            volumes("A c v12 c V126 ) c (20 c v+2 v16 c v)10 V100 ( c\nG v17 c ) c");
+        assert_eq!(
+            vec![
+                Some(108),
+                Some(117),
+                Some(127),
+                Some(107),
+                Some(127),
+                Some(106),
+                Some(8),
+                Some(9)
+            ],
+            volumes
+        );
+        assert_eq!(
+            vec![SemanticError::OutOfRange("v".to_string(), 17, 0, 15)],
+            errors
+        );
+    }
+
+    #[test]
+    fn test_resolve_volume_pcm_extend() {
+        let (volumes, _) = volumes("#PCMVolume Extend\nJ v12 c c\nJ ( c");
+        assert_eq!(vec![Some(144), Some(144), Some(128)], volumes);
+    }
+
+    #[test]
+    fn test_resolve_volume_attenuated() {
+        // FM is lowered by 16 of the default target
+        let mml = "A c V127 c\nG c\nJ v16 c\n";
+        let (volumes, _) = volumes_on(mml, TargetProfile::default());
+        assert_eq!(vec![Some(101), Some(119), Some(8), Some(255)], volumes);
+
+        let mml = "#VolumeDown F-16,S128,P+64\n".to_string() + mml;
+        let (volumes, _) = volumes_on(&mml, TargetProfile::default());
+        assert_eq!(vec![Some(108), Some(127), Some(4), Some(191)], volumes);
+    }
+
+    #[test]
+    fn test_resolve_volume_shift() {
+        let mut state = VolumeState::new(Chip::Ssg, false);
+        state.shift(-3);
+        assert_eq!(5, state.volume);
+        state.shift(20);
+        assert_eq!(15, state.volume);
+    }
+}